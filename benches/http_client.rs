@@ -0,0 +1,113 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+//! Measures the per-invocation overhead of sending the response to AWS CloudFormation, comparing a
+//! handler that is created anew for every invocation, and thus builds a new HTTP client and
+//! connection every time, with a single handler reusing its client, as in a warm AWS Lambda.
+//!
+//! Run with `cargo bench --bench http_client`.
+
+extern crate cfn_resource_provider;
+extern crate tokio_core;
+
+use cfn_resource_provider::*;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Core;
+
+const INVOCATIONS: u32 = 200;
+
+/// Starts a minimal HTTP server answering every request with `200 OK`, keeping connections alive.
+fn serve() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/presigned-url", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut reader = BufReader::new(stream.unwrap());
+            thread::spawn(move || loop {
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let mut header = line.splitn(2, ':');
+                    if header
+                        .next()
+                        .unwrap()
+                        .eq_ignore_ascii_case("content-length")
+                    {
+                        content_length = header.next().unwrap().trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                reader
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .unwrap();
+            });
+        }
+    });
+    url
+}
+
+fn request(response_url: &str) -> CfnRequest<Ignored> {
+    CfnRequest::Create {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url: response_url.to_owned(),
+        resource_type: "Custom::Benchmark".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        resource_properties: Ignored,
+    }
+}
+
+/// Runs the given number of invocations, returning the mean duration of a single one.
+fn measure<F>(core: &mut Core, invocations: u32, mut invoke: F) -> Duration
+where
+    F: FnMut(&mut Core),
+{
+    let started = Instant::now();
+    for _ in 0..invocations {
+        invoke(core);
+    }
+    started.elapsed() / invocations
+}
+
+fn main() {
+    let response_url = serve();
+    let mut core = Core::new().unwrap();
+
+    let new_handler = |core: &mut Core| {
+        let processor = Provider::new().process(|_event: CfnRequest<Ignored>| Ok(None::<()>));
+        core.run(processor(request(&response_url))).unwrap();
+    };
+    let processor = Provider::new().process(|_event: CfnRequest<Ignored>| Ok(None::<()>));
+    let shared_handler = |core: &mut Core| {
+        core.run(processor(request(&response_url))).unwrap();
+    };
+
+    // Warm up, such that neither measurement pays for one-time initialization.
+    measure(&mut core, 10, new_handler);
+    measure(&mut core, 10, shared_handler);
+
+    let new = measure(&mut core, INVOCATIONS, new_handler);
+    let shared = measure(&mut core, INVOCATIONS, shared_handler);
+    println!("new client per invocation: {:>10.1?} per invocation", new);
+    println!(
+        "shared client:             {:>10.1?} per invocation",
+        shared
+    );
+}
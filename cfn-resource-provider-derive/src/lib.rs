@@ -0,0 +1,577 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+#![deny(missing_docs)]
+
+//! # cfn-resource-provider-derive
+//!
+//! This crate provides the derive and attribute macros for
+//! [`cfn-resource-provider`][cfn-resource-provider]. You should not depend on it directly, but
+//! rather enable the `derive` feature of `cfn-resource-provider`, which re-exports the macros.
+//!
+//! [cfn-resource-provider]: https://docs.rs/cfn-resource-provider
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, AttributeArgs, Data, DataStruct, DeriveInput, Error, Field, Fields, GenericArgument,
+    Lit, LitStr, Meta, NestedMeta, Path, PathArguments, Type,
+};
+
+/// The separator used to join multiple suffix fields if none was specified.
+const DEFAULT_SEPARATOR: &str = "/";
+
+/// Derives `PhysicalResourceIdSuffixProvider` for a struct, building the suffix from the fields
+/// marked with `#[suffix]`.
+///
+/// Every marked field has to implement `Display`. If multiple fields are marked, their values are
+/// joined in declaration order, separated by a slash. The separator can be changed using
+/// `#[suffix(separator = "...")]` on the struct. Fields of type `Option<T>` are skipped if they are
+/// `None`.
+///
+/// The generated code refers to `::cfn_resource_provider`. If you renamed the crate in your
+/// `Cargo.toml`, specify the path it is available at using `#[suffix(crate = "...")]` on the
+/// struct, e.g. `#[suffix(crate = "::cfn")]`.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[derive(PhysicalResourceIdSuffix)]
+/// #[suffix(separator = "-")]
+/// struct MyResourceProperties {
+///     #[suffix]
+///     bucket_name: String,
+///     #[suffix]
+///     prefix: Option<String>,
+///     tags: Vec<String>,
+/// }
+/// ```
+#[proc_macro_derive(PhysicalResourceIdSuffix, attributes(suffix))]
+pub fn derive_physical_resource_id_suffix(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_physical_resource_id_suffix(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_physical_resource_id_suffix(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "`PhysicalResourceIdSuffix` can only be derived for structs",
+            ))
+        }
+    };
+
+    let options = container_options(&input.attrs)?;
+    let separator = options.separator;
+    let krate = options.krate.unwrap_or_else(default_crate_path);
+    let parts = suffix_parts(fields)?;
+    if parts.is_empty() {
+        return Err(Error::new(
+            input.ident.span(),
+            "at least one field has to be marked with `#[suffix]`",
+        ));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::PhysicalResourceIdSuffixProvider
+            for #ident #ty_generics #where_clause
+        {
+            fn physical_resource_id_suffix(&self) -> ::std::string::String {
+                let mut parts: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                #(#parts)*
+                parts.join(#separator)
+            }
+        }
+    })
+}
+
+/// Turns a struct into a complete custom resource, implementing `CustomResource`,
+/// `PhysicalResourceIdSuffixProvider`, `CreateOnlyProperties`, `SensitiveProperties` and
+/// `Deserialize` for it.
+///
+/// The resource type the struct belongs to has to be specified as `type = "..."`. Requests for a
+/// different resource type are rejected by `process_custom_resource`.
+///
+/// The properties are deserialized from their `PascalCase` names, as is customary for AWS
+/// CloudFormation. A different name can be specified using `#[property(rename = "...")]` on the
+/// field. While deserializing, strings are coerced into the booleans and numbers the fields expect
+/// (see `from_value_coerced`). Fields of type `Option<T>` are optional, all other fields are
+/// required. All missing or invalid properties are reported at once.
+///
+/// Fields marked with `#[suffix]` make up the physical resource ID suffix, just like with
+/// `#[derive(PhysicalResourceIdSuffix)]`. If no field is marked, the suffix is empty.
+///
+/// Fields marked with `#[create_only]` are reported by the generated `CreateOnlyProperties`
+/// implementation if an Update changes them. These fields have to implement `PartialEq`.
+///
+/// Fields marked with `#[sensitive]` are reported by the generated `SensitiveProperties`
+/// implementation, such that their values are redacted from failure reasons. These fields have to
+/// implement `Serialize`.
+///
+/// The generated code refers to `::cfn_resource_provider`. If you renamed the crate in your
+/// `Cargo.toml`, specify the path it is available at as `crate = "..."`, e.g.
+/// `#[custom_resource(type = "Custom::Bucket", crate = "::cfn")]`.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[custom_resource(type = "Custom::Bucket")]
+/// #[derive(Debug)]
+/// struct BucketProperties {
+///     #[suffix]
+///     #[create_only]
+///     bucket_name: String,
+///     #[property(rename = "TTL")]
+///     ttl: u32,
+///     #[sensitive]
+///     encryption_key: Option<String>,
+///     versioned: Option<bool>,
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn custom_resource(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_custom_resource(&args, input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_custom_resource(
+    args: &[NestedMeta],
+    mut input: DeriveInput,
+) -> Result<TokenStream2, Error> {
+    let (resource_type, krate) = resource_args(args)?;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "`#[custom_resource]` does not support generic structs",
+        ));
+    }
+    let fields = match input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(ref fields),
+            ..
+        }) => fields,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "`#[custom_resource]` can only be used on structs with named fields",
+            ))
+        }
+    };
+
+    let options = container_options(&input.attrs)?;
+    let separator = options.separator;
+    let krate = krate.or(options.krate).unwrap_or_else(default_crate_path);
+    let parts = suffix_parts(&Fields::Named(fields.clone()))?;
+
+    let mut bindings = Vec::new();
+    let mut members = Vec::new();
+    let mut create_only = Vec::new();
+    let mut sensitive = Vec::new();
+    for field in fields.named.iter() {
+        let ident = field.ident.as_ref().expect("named fields have identifiers");
+        let ty = &field.ty;
+        let name = property_name(field)?;
+        if is_marked(&field.attrs, "create_only")? {
+            create_only.push(quote! {
+                changes.compare(#name, &self.#ident, &old.#ident);
+            });
+        }
+        if is_marked(&field.attrs, "sensitive")? {
+            sensitive.push(quote! {
+                values.add(&self.#ident);
+            });
+        }
+        bindings.push(if is_option(ty) {
+            quote! {
+                let #ident: #ty = properties.optional(#name);
+            }
+        } else {
+            quote! {
+                let #ident: ::std::option::Option<#ty> = properties.required(#name);
+            }
+        });
+        members.push(if is_option(ty) {
+            quote!(#ident)
+        } else {
+            quote!(#ident: #ident.expect("required properties are present"))
+        });
+    }
+
+    // The helper attributes are only meaningful to this macro, so they must not be emitted.
+    input.attrs.retain(|attr| !attr.path.is_ident("suffix"));
+    if let Data::Struct(ref mut data) = input.data {
+        for field in data.fields.iter_mut() {
+            field.attrs.retain(|attr| {
+                !attr.path.is_ident("suffix")
+                    && !attr.path.is_ident("property")
+                    && !attr.path.is_ident("create_only")
+                    && !attr.path.is_ident("sensitive")
+            });
+        }
+    }
+
+    let ident = &input.ident;
+    Ok(quote! {
+        #input
+
+        impl #krate::CustomResource for #ident {
+            const RESOURCE_TYPE: &'static str = #resource_type;
+        }
+
+        impl #krate::PhysicalResourceIdSuffixProvider for #ident {
+            fn physical_resource_id_suffix(&self) -> ::std::string::String {
+                let mut parts: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                #(#parts)*
+                parts.join(#separator)
+            }
+        }
+
+        impl #krate::CreateOnlyProperties for #ident {
+            #[allow(unused_variables)]
+            fn compare_create_only(
+                &self,
+                old: &Self,
+                changes: &mut #krate::CreateOnlyChanges,
+            ) {
+                #(#create_only)*
+            }
+        }
+
+        impl #krate::SensitiveProperties for #ident {
+            #[allow(unused_variables)]
+            fn sensitive_values(&self, values: &mut #krate::SensitiveValues) {
+                #(#sensitive)*
+            }
+        }
+
+        impl<'de> #krate::__private::Deserialize<'de> for #ident {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: #krate::__private::Deserializer<'de>,
+            {
+                let mut properties =
+                    #krate::__private::Properties::deserialize(deserializer)?;
+                #(#bindings)*
+                properties.finish()?;
+                ::std::result::Result::Ok(#ident {
+                    #(#members,)*
+                })
+            }
+        }
+    })
+}
+
+/// Retrieves the resource type from the `type = "..."` argument of `#[custom_resource]`, and the
+/// path to `cfn_resource_provider` from the optional `crate = "..."` argument.
+fn resource_args(args: &[NestedMeta]) -> Result<(String, Option<Path>), Error> {
+    let mut resource_type = None;
+    let mut krate = None;
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(ref name_value))
+                if name_value.path.is_ident("crate") =>
+            {
+                crate_path(&mut krate, name_value)?
+            }
+            NestedMeta::Meta(Meta::NameValue(ref name_value))
+                if name_value.path.is_ident("type") =>
+            {
+                match name_value.lit {
+                    Lit::Str(ref lit) if resource_type.is_none() => {
+                        resource_type = Some(lit.value())
+                    }
+                    Lit::Str(_) => {
+                        return Err(Error::new(
+                            name_value.span(),
+                            "the resource type may only be specified once",
+                        ))
+                    }
+                    ref lit => {
+                        return Err(Error::new(
+                            lit.span(),
+                            "the resource type has to be a string literal",
+                        ))
+                    }
+                }
+            }
+            arg => {
+                return Err(Error::new(
+                    arg.span(),
+                    "expected `type = \"...\"` or `crate = \"...\"`",
+                ))
+            }
+        }
+    }
+    let resource_type = resource_type.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "the resource type has to be specified, e.g. `#[custom_resource(type = \"Custom::MyResource\")]`",
+        )
+    })?;
+    Ok((resource_type, krate))
+}
+
+/// The path the generated code refers to `cfn_resource_provider` with, unless overridden.
+fn default_crate_path() -> Path {
+    parse_quote!(::cfn_resource_provider)
+}
+
+/// Parses the path of a `crate = "..."` argument into `krate`, which may only be set once.
+fn crate_path(krate: &mut Option<Path>, name_value: &syn::MetaNameValue) -> Result<(), Error> {
+    match name_value.lit {
+        Lit::Str(ref lit) if krate.is_none() => {
+            *krate = Some(parse_path(lit)?);
+            Ok(())
+        }
+        Lit::Str(_) => Err(Error::new(
+            name_value.span(),
+            "the crate path may only be specified once",
+        )),
+        ref lit => Err(Error::new(
+            lit.span(),
+            "the crate path has to be a string literal",
+        )),
+    }
+}
+
+fn parse_path(lit: &LitStr) -> Result<Path, Error> {
+    lit.parse()
+        .map_err(|_| Error::new(lit.span(), "the crate path has to be a valid path"))
+}
+
+/// Retrieves the name of the property a field is deserialized from, which is either given through
+/// `#[property(rename = "...")]` or the `PascalCase` version of the field name.
+fn property_name(field: &Field) -> Result<String, Error> {
+    let mut name = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("property"))
+    {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    "expected `#[property(rename = \"...\")]`",
+                ))
+            }
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(ref name_value))
+                    if name_value.path.is_ident("rename") =>
+                {
+                    match name_value.lit {
+                        Lit::Str(ref lit) if name.is_none() => name = Some(lit.value()),
+                        Lit::Str(_) => {
+                            return Err(Error::new(
+                                name_value.span(),
+                                "the property name may only be specified once",
+                            ))
+                        }
+                        ref lit => {
+                            return Err(Error::new(
+                                lit.span(),
+                                "the property name has to be a string literal",
+                            ))
+                        }
+                    }
+                }
+                nested => return Err(Error::new(nested.span(), "expected `rename = \"...\"`")),
+            }
+        }
+    }
+    Ok(match name {
+        Some(name) => name,
+        None => {
+            let ident = field.ident.as_ref().expect("named fields have identifiers");
+            pascal_case(&ident.to_string())
+        }
+    })
+}
+
+/// Converts a `snake_case` field name into `PascalCase`.
+fn pascal_case(name: &str) -> String {
+    name.trim_start_matches("r#")
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Generates the statements pushing the values of all `#[suffix]` fields onto `parts`.
+fn suffix_parts(fields: &Fields) -> Result<Vec<TokenStream2>, Error> {
+    let mut parts = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        if !is_suffix_field(&field.attrs)? {
+            continue;
+        }
+        let member = match field.ident {
+            Some(ref ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(index);
+                quote!(#index)
+            }
+        };
+        parts.push(if is_option(&field.ty) {
+            quote! {
+                if let ::std::option::Option::Some(ref value) = self.#member {
+                    parts.push(::std::string::ToString::to_string(value));
+                }
+            }
+        } else {
+            quote! {
+                parts.push(::std::string::ToString::to_string(&self.#member));
+            }
+        });
+    }
+    Ok(parts)
+}
+
+/// The options given through `#[suffix(...)]` attributes on the container.
+struct ContainerOptions {
+    separator: String,
+    krate: Option<Path>,
+}
+
+/// Retrieves the separator and crate path from `#[suffix(separator = "...", crate = "...")]`
+/// attributes on the container.
+fn container_options(attrs: &[Attribute]) -> Result<ContainerOptions, Error> {
+    let mut separator = None;
+    let mut krate = None;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("suffix")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    "expected `#[suffix(separator = \"...\")]`",
+                ))
+            }
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(ref name_value))
+                    if name_value.path.is_ident("crate") =>
+                {
+                    crate_path(&mut krate, name_value)?
+                }
+                NestedMeta::Meta(Meta::NameValue(ref name_value))
+                    if name_value.path.is_ident("separator") =>
+                {
+                    match name_value.lit {
+                        Lit::Str(ref lit) if separator.is_none() => separator = Some(lit.value()),
+                        Lit::Str(_) => {
+                            return Err(Error::new(
+                                name_value.span(),
+                                "the separator may only be specified once",
+                            ))
+                        }
+                        ref lit => {
+                            return Err(Error::new(
+                                lit.span(),
+                                "the separator has to be a string literal",
+                            ))
+                        }
+                    }
+                }
+                nested => {
+                    return Err(Error::new(
+                        nested.span(),
+                        "expected `separator = \"...\"` or `crate = \"...\"`",
+                    ))
+                }
+            }
+        }
+    }
+    Ok(ContainerOptions {
+        separator: separator.unwrap_or_else(|| DEFAULT_SEPARATOR.to_owned()),
+        krate,
+    })
+}
+
+/// Checks whether a field is marked with a plain `#[suffix]` attribute.
+fn is_suffix_field(attrs: &[Attribute]) -> Result<bool, Error> {
+    let mut is_suffix_field = false;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("suffix")) {
+        match attr.parse_meta()? {
+            Meta::Path(_) => is_suffix_field = true,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    "expected `#[suffix]` without arguments on fields",
+                ))
+            }
+        }
+    }
+    Ok(is_suffix_field)
+}
+
+/// Checks whether a field is marked with a plain attribute of the given name, e.g. `#[create_only]`.
+fn is_marked(attrs: &[Attribute], name: &str) -> Result<bool, Error> {
+    let mut is_marked = false;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident(name)) {
+        match attr.parse_meta()? {
+            Meta::Path(_) => is_marked = true,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    format!("expected `#[{}]` without arguments", name),
+                ))
+            }
+        }
+    }
+    Ok(is_marked)
+}
+
+/// Checks whether a type is an `Option<T>`, in which case `None` values are left out of the suffix.
+fn is_option(ty: &Type) -> bool {
+    let path = match ty {
+        Type::Path(ref type_path) if type_path.qself.is_none() => &type_path.path,
+        _ => return false,
+    };
+    let segment = match path.segments.last() {
+        Some(segment) => segment,
+        None => return false,
+    };
+    if segment.ident != "Option" {
+        return false;
+    }
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref arguments) => match arguments.args.first() {
+            Some(GenericArgument::Type(_)) => arguments.args.len() == 1,
+            _ => false,
+        },
+        _ => false,
+    }
+}
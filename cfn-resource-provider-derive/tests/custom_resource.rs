@@ -0,0 +1,240 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+extern crate cfn_resource_provider;
+extern crate cfn_resource_provider_derive;
+#[macro_use]
+extern crate failure;
+extern crate futures;
+#[macro_use]
+extern crate serde_json;
+extern crate tokio_core;
+
+use cfn_resource_provider::{
+    process_custom_resource, CfnRequest, CreateOnlyChanges, CreateOnlyProperties, CustomResource,
+    PhysicalResourceIdSuffixProvider, Redactor, SensitiveProperties, SensitiveValues,
+};
+use cfn_resource_provider_derive::custom_resource;
+use failure::Error;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use tokio_core::reactor::Core;
+
+#[custom_resource(type = "Custom::Bucket")]
+#[derive(Debug, PartialEq)]
+struct BucketProperties {
+    #[suffix]
+    #[create_only]
+    bucket_name: String,
+    #[property(rename = "TTL")]
+    ttl: u32,
+    versioned: Option<bool>,
+    tags: Vec<String>,
+    #[sensitive]
+    encryption_key: Option<String>,
+}
+
+#[test]
+fn resource_type() {
+    assert_eq!(BucketProperties::RESOURCE_TYPE, "Custom::Bucket");
+}
+
+#[test]
+fn deserialize_coerced() {
+    let properties: BucketProperties = serde_json::from_value(json!({
+        "ServiceToken": "arn:aws:lambda:us-east-1:123456789012:function:provider",
+        "BucketName": "bucket",
+        "TTL": "300",
+        "Versioned": "true",
+        "Tags": ["a", "b"],
+    }))
+    .unwrap();
+    assert_eq!(
+        properties,
+        BucketProperties {
+            bucket_name: "bucket".to_owned(),
+            ttl: 300,
+            versioned: Some(true),
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            encryption_key: None,
+        }
+    );
+}
+
+#[test]
+fn optional_properties() {
+    let properties: BucketProperties = serde_json::from_value(json!({
+        "BucketName": "bucket",
+        "TTL": 300,
+        "Tags": [],
+    }))
+    .unwrap();
+    assert_eq!(properties.versioned, None);
+}
+
+#[test]
+fn reports_all_violations() {
+    let error = serde_json::from_value::<BucketProperties>(json!({
+        "TTL": "forever",
+        "Versioned": "true",
+    }))
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("missing required property `BucketName`"));
+    assert!(error.contains("invalid property `TTL`"));
+    assert!(error.contains("missing required property `Tags`"));
+    assert!(!error.contains("Versioned"));
+}
+
+#[test]
+fn suffix() {
+    #[custom_resource(type = "Custom::Object")]
+    #[suffix(separator = "-")]
+    struct ObjectProperties {
+        #[suffix]
+        bucket: String,
+        #[suffix]
+        key: String,
+    }
+
+    #[custom_resource(type = "Custom::Empty")]
+    struct EmptyProperties {}
+
+    let properties: ObjectProperties =
+        serde_json::from_value(json!({ "Bucket": "bucket", "Key": "key" })).unwrap();
+    assert_eq!(properties.physical_resource_id_suffix(), "bucket-key");
+    let properties: EmptyProperties = serde_json::from_value(json!({})).unwrap();
+    assert_eq!(properties.physical_resource_id_suffix(), "");
+}
+
+#[test]
+fn check_resource_type() {
+    let request: CfnRequest<BucketProperties> = serde_json::from_value(json!({
+        "RequestType": "Create",
+        "RequestId": "unique id for this create request",
+        "ResponseURL": "pre-signed-url-for-create-response",
+        "ResourceType": "Custom::Other",
+        "LogicalResourceId": "MyCustomResource",
+        "StackId": "arn:aws:cloudformation:us-east-1:123456789012:stack/stack-name/guid",
+        "ResourceProperties": {
+            "BucketName": "bucket",
+            "TTL": "300",
+            "Tags": [],
+        },
+    }))
+    .unwrap();
+    assert!(request.check_resource_type().is_err());
+}
+
+#[test]
+fn create_only() {
+    let old: BucketProperties = serde_json::from_value(json!({
+        "BucketName": "bucket",
+        "TTL": "300",
+        "Tags": [],
+    }))
+    .unwrap();
+    let new: BucketProperties = serde_json::from_value(json!({
+        "BucketName": "other-bucket",
+        "TTL": "600",
+        "Tags": [],
+    }))
+    .unwrap();
+
+    let mut changes = CreateOnlyChanges::default();
+    new.compare_create_only(&old, &mut changes);
+    assert_eq!(changes.properties(), &["BucketName"]);
+}
+
+#[test]
+fn sensitive() {
+    let properties: BucketProperties = serde_json::from_value(json!({
+        "BucketName": "bucket",
+        "TTL": "300",
+        "Tags": [],
+        "EncryptionKey": "s3cr3t",
+    }))
+    .unwrap();
+
+    let mut values = SensitiveValues::new();
+    properties.sensitive_values(&mut values);
+    assert_eq!(
+        Redactor::new().redact("invalid key s3cr3t for bucket", &values),
+        "invalid key *** for bucket"
+    );
+}
+
+/// Accepts a single response on a local port, returning its URL and a receiver for the body.
+fn capture_response() -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/presigned-url", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let mut header = line.splitn(2, ':');
+            if header
+                .next()
+                .unwrap()
+                .eq_ignore_ascii_case("content-length")
+            {
+                content_length = header.next().unwrap().trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        sender.send(String::from_utf8(body).unwrap()).unwrap();
+    });
+    (url, receiver)
+}
+
+#[test]
+fn sensitive_redacted_from_response() {
+    let (response_url, receiver) = capture_response();
+    let request: CfnRequest<BucketProperties> = serde_json::from_value(json!({
+        "RequestType": "Create",
+        "RequestId": "REQUEST-ID",
+        "ResponseURL": response_url,
+        "ResourceType": "Custom::Bucket",
+        "LogicalResourceId": "LOGICAL-RESOURCE-ID",
+        "StackId": "STACK-ID",
+        "ResourceProperties": {
+            "BucketName": "bucket",
+            "TTL": "300",
+            "Tags": [],
+            "EncryptionKey": "s3cr3t",
+        },
+    }))
+    .unwrap();
+
+    let processor = process_custom_resource(|request: CfnRequest<BucketProperties>| {
+        let key = request.resource_properties().encryption_key.clone();
+        Err::<Option<()>, Error>(format_err!("invalid key {}", key.unwrap()))
+    });
+    let mut core = Core::new().unwrap();
+    assert!(core.run(processor(request)).is_err());
+
+    let response: Value = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response["Status"], "FAILED");
+    assert_eq!(response["Reason"], "invalid key ***");
+}
@@ -0,0 +1,114 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+extern crate cfn_resource_provider;
+#[macro_use]
+extern crate cfn_resource_provider_derive;
+
+use cfn_resource_provider::PhysicalResourceIdSuffixProvider;
+
+#[test]
+fn single_field() {
+    #[derive(PhysicalResourceIdSuffix)]
+    #[allow(dead_code)]
+    struct Properties {
+        #[suffix]
+        name: String,
+        other: String,
+    }
+
+    let properties = Properties {
+        name: "name".to_owned(),
+        other: "other".to_owned(),
+    };
+    assert_eq!(properties.physical_resource_id_suffix(), "name");
+}
+
+#[test]
+fn multiple_fields_default_separator() {
+    #[derive(PhysicalResourceIdSuffix)]
+    struct Properties {
+        #[suffix]
+        name: String,
+        #[suffix]
+        version: u32,
+    }
+
+    let properties = Properties {
+        name: "name".to_owned(),
+        version: 2,
+    };
+    assert_eq!(properties.physical_resource_id_suffix(), "name/2");
+}
+
+#[test]
+fn multiple_fields_custom_separator() {
+    #[derive(PhysicalResourceIdSuffix)]
+    #[suffix(separator = "-")]
+    struct Properties {
+        #[suffix]
+        name: String,
+        #[suffix]
+        version: u32,
+    }
+
+    let properties = Properties {
+        name: "name".to_owned(),
+        version: 2,
+    };
+    assert_eq!(properties.physical_resource_id_suffix(), "name-2");
+}
+
+#[test]
+fn option_fields() {
+    #[derive(PhysicalResourceIdSuffix)]
+    struct Properties {
+        #[suffix]
+        prefix: Option<String>,
+        #[suffix]
+        name: String,
+        #[suffix]
+        version: Option<u32>,
+    }
+
+    let properties = Properties {
+        prefix: None,
+        name: "name".to_owned(),
+        version: Some(2),
+    };
+    assert_eq!(properties.physical_resource_id_suffix(), "name/2");
+
+    let properties = Properties {
+        prefix: Some("prefix".to_owned()),
+        name: "name".to_owned(),
+        version: None,
+    };
+    assert_eq!(properties.physical_resource_id_suffix(), "prefix/name");
+}
+
+#[test]
+fn tuple_struct() {
+    #[derive(PhysicalResourceIdSuffix)]
+    #[allow(dead_code)]
+    struct Properties(#[suffix] String, String, #[suffix] u32);
+
+    let properties = Properties("name".to_owned(), "other".to_owned(), 2);
+    assert_eq!(properties.physical_resource_id_suffix(), "name/2");
+}
+
+#[test]
+fn generic_struct() {
+    #[derive(PhysicalResourceIdSuffix)]
+    struct Properties<T: ::std::fmt::Display> {
+        #[suffix]
+        value: T,
+    }
+
+    let properties = Properties { value: 42 };
+    assert_eq!(properties.physical_resource_id_suffix(), "42");
+}
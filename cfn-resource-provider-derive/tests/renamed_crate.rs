@@ -0,0 +1,48 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+extern crate cfn_resource_provider as cfn;
+#[macro_use]
+extern crate cfn_resource_provider_derive;
+#[macro_use]
+extern crate serde_json;
+
+use cfn::{CustomResource, PhysicalResourceIdSuffixProvider};
+use cfn_resource_provider_derive::custom_resource;
+
+#[test]
+fn derive_with_crate_path() {
+    #[derive(PhysicalResourceIdSuffix)]
+    #[suffix(separator = "-", crate = "::cfn")]
+    struct Properties {
+        #[suffix]
+        name: String,
+        #[suffix]
+        version: u32,
+    }
+
+    let properties = Properties {
+        name: "name".to_owned(),
+        version: 2,
+    };
+    assert_eq!(properties.physical_resource_id_suffix(), "name-2");
+}
+
+#[custom_resource(type = "Custom::Renamed", crate = "::cfn")]
+#[derive(Debug, PartialEq)]
+struct RenamedProperties {
+    #[suffix]
+    name: String,
+}
+
+#[test]
+fn custom_resource_with_crate_path() {
+    assert_eq!(RenamedProperties::RESOURCE_TYPE, "Custom::Renamed");
+    let properties: RenamedProperties = serde_json::from_value(json!({ "Name": "name" })).unwrap();
+    assert_eq!(properties.physical_resource_id_suffix(), "name");
+}
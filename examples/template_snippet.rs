@@ -0,0 +1,64 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+//! Prints a sample AWS CloudFormation template snippet for a custom resource, as YAML or JSON.
+//!
+//! Ship a binary like this one alongside your custom resource, so consumers can generate a correct
+//! resource declaration:
+//!
+//! ```text
+//! cargo run --example template_snippet --features template -- [yaml|json]
+//! ```
+
+extern crate cfn_resource_provider;
+#[macro_use]
+extern crate schemars;
+#[macro_use]
+extern crate serde;
+
+use cfn_resource_provider::TemplateSnippet;
+use std::env;
+use std::process;
+
+#[allow(dead_code)]
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+struct BucketProperties {
+    bucket_name: String,
+    versioned: Option<bool>,
+    tags: Vec<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+struct BucketData {
+    arn: String,
+    domain_name: String,
+}
+
+fn main() {
+    let snippet = TemplateSnippet::new::<BucketProperties>("Custom::Bucket")
+        .with_logical_resource_id("Bucket")
+        .with_data::<BucketData>();
+
+    match env::args().nth(1).as_deref() {
+        None | Some("yaml") => match snippet.to_yaml() {
+            Ok(yaml) => println!("{}", yaml),
+            Err(e) => {
+                eprintln!("failed to render the snippet: {}", e);
+                process::exit(1);
+            }
+        },
+        Some("json") => println!("{}", snippet.to_json()),
+        Some(format) => {
+            eprintln!("unknown format `{}`, expected `yaml` or `json`", format);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, Deserializer, IntoDeserializer, Unexpected, Visitor,
+};
+use serde_json::{Error, Value};
+
+/// Deserializes a value of type `T` from a JSON value, coercing strings into the booleans and
+/// numbers `T` expects.
+///
+/// AWS CloudFormation passes all scalar values of the resource properties as strings, i.e. a
+/// property specified as `Size: 5` in the template arrives as `"Size": "5"`. Deserializing such
+/// properties into a `u32` fails with plain [Serde], which is where this function comes in: whenever
+/// `T` expects a boolean or a number and the JSON value is a string, the string is parsed instead.
+/// (The reverse applies as well: numbers and booleans are accepted where `T` expects a string.)
+/// The coercion applies recursively to sequences, maps and nested structs.
+///
+/// Enums are deserialized without coercion.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # #[macro_use]
+/// # extern crate serde;
+/// # #[macro_use]
+/// # extern crate serde_json;
+/// # use cfn_resource_provider::*;
+/// #[derive(Debug, PartialEq, Deserialize)]
+/// struct MyResourceProperties {
+///     name: String,
+///     size: u32,
+///     enabled: Option<bool>,
+/// }
+///
+/// # fn main() {
+/// let properties: MyResourceProperties = from_value_coerced(json!({
+///     "name": "1234",
+///     "size": "5",
+///     "enabled": "true",
+/// })).unwrap();
+///
+/// assert_eq!(
+///     properties,
+///     MyResourceProperties {
+///         name: "1234".to_owned(),
+///         size: 5,
+///         enabled: Some(true),
+///     }
+/// );
+/// # }
+/// ```
+///
+/// [Serde]: https://serde.rs/
+pub fn from_value_coerced<T>(value: Value) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(Coerce(value))
+}
+
+/// Deserializes a value of type `T` like [`from_value_coerced`] does, but from any deserializer.
+///
+/// This function is meant to be used with Serde's `deserialize_with` attribute, enabling the
+/// coercion for single fields of your resource properties:
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # #[macro_use]
+/// # extern crate serde;
+/// # #[macro_use]
+/// # extern crate serde_json;
+/// #[derive(Debug, PartialEq, Deserialize)]
+/// struct MyResourceProperties {
+///     #[serde(deserialize_with = "cfn_resource_provider::deserialize_coerced")]
+///     size: u32,
+/// }
+/// # fn main() {
+/// # let properties: MyResourceProperties = serde_json::from_value(json!({ "size": "5" })).unwrap();
+/// # assert_eq!(properties, MyResourceProperties { size: 5 });
+/// # }
+/// ```
+///
+/// [`from_value_coerced`]: fn.from_value_coerced.html
+pub fn deserialize_coerced<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = Value::deserialize(deserializer)?;
+    from_value_coerced(value).map_err(de::Error::custom)
+}
+
+/// A deserializer wrapping a JSON value, coercing strings into booleans and numbers (and vice
+/// versa) whenever the type being deserialized asks for them.
+struct Coerce(Value);
+
+impl<'de> IntoDeserializer<'de, Error> for Coerce {
+    type Deserializer = Coerce;
+
+    fn into_deserializer(self) -> Coerce {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $ty:ty, $visit:ident;)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where
+                V: Visitor<'de>,
+            {
+                match self.0 {
+                    Value::String(ref value) => match value.trim().parse::<$ty>() {
+                        Ok(parsed) => visitor.$visit(parsed),
+                        Err(_) => Err(de::Error::invalid_value(Unexpected::Str(value), &visitor)),
+                    },
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Coerce {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Array(values) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(values.into_iter().map(Coerce));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Object(values) => {
+                let mut map = MapDeserializer::<_, Error>::new(
+                    values.into_iter().map(|(key, value)| (key, Coerce(value))),
+                );
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => bool, visit_bool;
+        deserialize_i8 => i64, visit_i64;
+        deserialize_i16 => i64, visit_i64;
+        deserialize_i32 => i64, visit_i64;
+        deserialize_i64 => i64, visit_i64;
+        deserialize_u8 => u64, visit_u64;
+        deserialize_u16 => u64, visit_u64;
+        deserialize_u32 => u64, visit_u64;
+        deserialize_u64 => u64, visit_u64;
+        deserialize_f32 => f64, visit_f64;
+        deserialize_f64 => f64, visit_f64;
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Bool(value) => visitor.visit_string(value.to_string()),
+            Value::Number(value) => visitor.visit_string(value.to_string()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        char bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Nested {
+        count: u8,
+        ratio: f64,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Properties {
+        name: String,
+        size: u32,
+        offset: i32,
+        enabled: bool,
+        optional: Option<bool>,
+        list: Vec<u16>,
+        map: HashMap<String, bool>,
+        nested: Nested,
+    }
+
+    #[test]
+    fn coerces_strings() {
+        let properties: Properties = from_value_coerced(json!({
+            "name": "1234",
+            "size": "5",
+            "offset": "-3",
+            "enabled": "false",
+            "optional": "true",
+            "list": ["1", "2"],
+            "map": { "key": "true" },
+            "nested": { "count": "7", "ratio": "0.5" },
+        }))
+        .unwrap();
+
+        let mut map = HashMap::new();
+        map.insert("key".to_owned(), true);
+        assert_eq!(
+            properties,
+            Properties {
+                name: "1234".to_owned(),
+                size: 5,
+                offset: -3,
+                enabled: false,
+                optional: Some(true),
+                list: vec![1, 2],
+                map,
+                nested: Nested {
+                    count: 7,
+                    ratio: 0.5,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn accepts_native_types() {
+        let properties: Properties = from_value_coerced(json!({
+            "name": 1234,
+            "size": 5,
+            "offset": -3,
+            "enabled": false,
+            "optional": null,
+            "list": [1, 2],
+            "map": {},
+            "nested": { "count": 7, "ratio": 0.5 },
+        }))
+        .unwrap();
+        assert_eq!(properties.name, "1234");
+        assert_eq!(properties.size, 5);
+        assert_eq!(properties.optional, None);
+    }
+
+    #[test]
+    fn rejects_invalid_strings() {
+        assert!(from_value_coerced::<u32>(json!("five")).is_err());
+        assert!(from_value_coerced::<u32>(json!("-5")).is_err());
+        assert!(from_value_coerced::<u8>(json!("256")).is_err());
+        assert!(from_value_coerced::<bool>(json!("yes")).is_err());
+    }
+
+    #[test]
+    fn deserialize_with() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Properties {
+            #[serde(deserialize_with = "deserialize_coerced")]
+            size: u32,
+        }
+
+        let properties: Properties = serde_json::from_value(json!({ "size": "5" })).unwrap();
+        assert_eq!(properties, Properties { size: 5 });
+    }
+}
@@ -0,0 +1,365 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use provider::{Measured, Responder};
+use raw::{deserialization_error, respond_malformed};
+use serde::de::{Deserialize, DeserializeOwned};
+use serde::ser::Serialize;
+use serde_json::{self, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tracing_futures::Instrument;
+use {
+    log_request, request_span, CfnRequest, PhysicalIdStrategy, PhysicalResourceIdSuffixProvider,
+    Provider, ResponseFields, SensitiveProperties, SensitiveValues,
+};
+
+/// The key under which the continuation is stored in the event scheduled for the next invocation.
+const CONTINUATION: &str = "CfnResourceProviderContinuation";
+
+/// The outcome of a single invocation of a long-running handler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress<T, S> {
+    /// The operation has finished, with the data to be serialized into the response (if any).
+    Done(Option<S>),
+    /// The operation is still in progress. The handler will be invoked again with the given state.
+    InProgress(T),
+}
+
+/// A scheduler re-invoking the AWS Lambda function for long-running operations.
+///
+/// The scheduler receives the event the function has to be invoked with next. It is up to the
+/// implementation when this happens, e.g. it could invoke the function asynchronously through the
+/// AWS Lambda API, or send the event to an Amazon SQS queue with a delivery delay.
+///
+/// [`LocalScheduler`] keeps the events in memory, which is useful for tests.
+///
+/// [`LocalScheduler`]: struct.LocalScheduler.html
+pub trait Scheduler: Send + Sync {
+    /// Schedules the next invocation with the given event.
+    fn schedule(&self, event: Value) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+}
+
+impl<T> Scheduler for Arc<T>
+where
+    T: Scheduler + ?Sized,
+{
+    fn schedule(&self, event: Value) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        (**self).schedule(event)
+    }
+}
+
+/// A [`Scheduler`] keeping the scheduled events in memory, to be retrieved and processed by the
+/// caller.
+///
+/// [`Scheduler`]: trait.Scheduler.html
+#[derive(Debug, Default)]
+pub struct LocalScheduler {
+    events: Mutex<VecDeque<Value>>,
+}
+
+impl LocalScheduler {
+    /// Creates a scheduler without any scheduled events.
+    pub fn new() -> LocalScheduler {
+        LocalScheduler::default()
+    }
+
+    /// Retrieves the event scheduled first, if any.
+    pub fn next(&self) -> Option<Value> {
+        self.events().pop_front()
+    }
+
+    /// The number of scheduled events.
+    pub fn len(&self) -> usize {
+        self.events().len()
+    }
+
+    /// Whether no events are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn events<'a>(&'a self) -> MutexGuard<'a, VecDeque<Value>> {
+        self.events.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Scheduler for LocalScheduler {
+    fn schedule(&self, event: Value) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        self.events().push_back(event);
+        Box::new(future::ok(()))
+    }
+}
+
+/// The state persisted between the invocations of a long-running operation.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Continuation<T> {
+    request: Value,
+    state: T,
+}
+
+/// Process an AWS CloudFormation custom resource request that can take longer than a single AWS
+/// Lambda invocation.
+///
+/// Your closure receives the request along with the state it returned from the previous
+/// invocation (`None` on the first invocation). If the operation has not finished yet, it returns
+/// [`Progress::InProgress`] with the state it needs to continue. The original request and this
+/// state are then handed to the [`Scheduler`], which re-invokes the AWS Lambda function with them
+/// later, and no response is sent to AWS CloudFormation yet. Once your closure returns
+/// [`Progress::Done`] or fails, or if the next invocation cannot be scheduled, the response is sent
+/// as [`process`] would.
+///
+/// The returned function accepts the raw JSON event, which is either the request sent by AWS
+/// CloudFormation or an event scheduled by a previous invocation. The result returned to the
+/// caller is `Ok(None)` as long as the operation is in progress. Should the request or the
+/// continuation not deserialize, AWS CloudFormation is informed about the failure right away, as
+/// [`process_json`] does.
+///
+/// Keep in mind that AWS CloudFormation only waits for the response until the custom resource
+/// times out, which is one hour by default.
+///
+/// Use [`Provider::process_continuable`] to configure how the requests are processed.
+///
+/// [`Progress::InProgress`]: enum.Progress.html#variant.InProgress
+/// [`Progress::Done`]: enum.Progress.html#variant.Done
+/// [`Scheduler`]: trait.Scheduler.html
+/// [`process`]: fn.process.html
+/// [`process_json`]: fn.process_json.html
+/// [`Provider::process_continuable`]: struct.Provider.html#method.process_continuable
+pub fn process_continuable<C, F, R, P, T, S>(
+    scheduler: C,
+    f: F,
+) -> impl Fn(Value) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    C: Scheduler + 'static,
+    F: Fn(CfnRequest<P>, Option<T>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Progress<T, S>, Error = Error> + Send + 'static,
+    R::Future: Send,
+    P: PhysicalResourceIdSuffixProvider + SensitiveProperties + DeserializeOwned + Send + 'static,
+    T: Serialize + DeserializeOwned + Send + 'static,
+    S: Serialize + Send + 'static,
+{
+    Provider::new().process_continuable(scheduler, f)
+}
+
+impl<T> Provider<T> {
+    /// Process AWS CloudFormation custom resource requests that can take longer than a single AWS
+    /// Lambda invocation with this configuration, as [`process_continuable`] would.
+    ///
+    /// The response URL is checked on every invocation, before your closure is invoked.
+    ///
+    /// [`process_continuable`]: fn.process_continuable.html
+    pub fn process_continuable<C, F, R, P, U, S>(
+        self,
+        scheduler: C,
+        f: F,
+    ) -> impl Fn(Value) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+        C: Scheduler + 'static,
+        F: Fn(CfnRequest<P>, Option<U>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Progress<U, S>, Error = Error> + Send + 'static,
+        R::Future: Send,
+        P: SensitiveProperties + DeserializeOwned + Send + 'static,
+        U: Serialize + DeserializeOwned + Send + 'static,
+        S: Serialize + Send + 'static,
+    {
+        let (strategy, responder) = self.into_parts();
+        let scheduler = Arc::new(scheduler);
+        move |event: Value| {
+            let (request_event, state) = match continuation::<U>(event) {
+                Ok(Ok(continuation)) => (continuation.request, Some(continuation.state)),
+                Ok(Err(event)) => (event, None),
+                // Without the continuation, the request might still be available to respond to.
+                Err((request_event, e)) => {
+                    let reason = format!("failed to deserialize the continuation: {}", e);
+                    return respond_malformed(&responder, &request_event, reason);
+                }
+            };
+            let request = match CfnRequest::<P>::deserialize(&request_event) {
+                Ok(request) => request,
+                Err(e) => {
+                    let reason = format!(
+                        "failed to deserialize the request: {}",
+                        deserialization_error::<P>(&request_event, &e)
+                    );
+                    return respond_malformed(&responder, &request_event, reason);
+                }
+            };
+            let span = request_span(&request);
+            let _entered = span.enter();
+            log_request(&request);
+            if let Err(e) = responder.check(request.response_url()) {
+                return Box::new(future::err(e));
+            }
+
+            let scheduler = scheduler.clone();
+            let responder = responder.clone();
+            let response_url = request.response_url().to_owned();
+            let physical_resource_id = request.physical_resource_id_with(&strategy);
+            let response_fields = ResponseFields::new(&request, physical_resource_id);
+            let measured = responder.measure(&request);
+            let sensitive_values = request.sensitive_values();
+            Box::new(
+                f(request, state)
+                    .into_future()
+                    .then(move |result| match result {
+                        Ok(Progress::InProgress(state)) => {
+                            let continuation = Continuation {
+                                request: request_event,
+                                state,
+                            };
+                            let scheduled = serde_json::to_value(continuation)
+                                .map_err(Error::from)
+                                .into_future()
+                                .and_then(move |continuation| {
+                                    scheduler.schedule(json!({ CONTINUATION: continuation }))
+                                });
+                            // Should scheduling the next invocation fail, there would be no one
+                            // left to respond to AWS CloudFormation, so we report the failure
+                            // right away.
+                            Either::A(scheduled.then(move |scheduled| match scheduled {
+                                Ok(()) => Either::A(future::ok(None)),
+                                Err(e) => Either::B(respond(
+                                    &responder,
+                                    response_url,
+                                    response_fields,
+                                    measured,
+                                    &sensitive_values,
+                                    Err(e),
+                                )),
+                            }))
+                        }
+                        Ok(Progress::Done(data)) => Either::B(respond(
+                            &responder,
+                            response_url,
+                            response_fields,
+                            measured,
+                            &sensitive_values,
+                            Ok(data),
+                        )),
+                        Err(e) => Either::B(respond(
+                            &responder,
+                            response_url,
+                            response_fields,
+                            measured,
+                            &sensitive_values,
+                            Err(e),
+                        )),
+                    })
+                    .instrument(span.clone()),
+            )
+        }
+    }
+}
+
+/// Extracts the continuation from a scheduled event, or returns the event as is if it is not a
+/// scheduled event. Should the continuation be malformed, the request it contains is returned
+/// along with the error, or `null` if there is none.
+fn continuation<T>(
+    mut event: Value,
+) -> Result<Result<Continuation<T>, Value>, (Value, serde_json::Error)>
+where
+    T: DeserializeOwned,
+{
+    let continuation = match event.as_object_mut() {
+        Some(object) => object.remove(CONTINUATION),
+        None => None,
+    };
+    match continuation {
+        Some(mut continuation) => match Continuation::deserialize(&continuation) {
+            Ok(continuation) => Ok(Ok(continuation)),
+            Err(e) => {
+                let request = match continuation.as_object_mut() {
+                    Some(continuation) => continuation.remove("Request"),
+                    None => None,
+                };
+                Err((request.unwrap_or(Value::Null), e))
+            }
+        },
+        None => Ok(Err(event)),
+    }
+}
+
+fn respond<S>(
+    responder: &Responder,
+    response_url: String,
+    response_fields: ResponseFields,
+    measured: Option<Measured>,
+    sensitive_values: &SensitiveValues,
+    result: Result<Option<S>, Error>,
+) -> impl Future<Item = Option<S>, Error = Error>
+where
+    S: Serialize,
+{
+    let cfn_response = responder.response(response_fields, &result, sensitive_values);
+    responder
+        .send(response_url, &cfn_response, measured)
+        .and_then(move |_| result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Ignored;
+
+    fn request() -> Value {
+        json!({
+            "RequestType": "Create",
+            "RequestId": "unique id for this create request",
+            "ResponseURL": "http://pre-signed-S3-url-for-response",
+            "ResourceType": "Custom::MyCustomResourceType",
+            "LogicalResourceId": "MyCustomResource",
+            "StackId": "arn:aws:cloudformation:us-east-1:123456789012:stack/stack-name/guid",
+            "ResourceProperties": {},
+        })
+    }
+
+    #[test]
+    fn in_progress_schedules_continuation() {
+        let scheduler = Arc::new(LocalScheduler::new());
+        let processor = process_continuable(
+            scheduler.clone(),
+            |_request: CfnRequest<Ignored>, state: Option<u32>| {
+                Ok(Progress::InProgress::<_, ()>(state.unwrap_or(0) + 1))
+            },
+        );
+
+        assert_eq!(processor(request()).wait().unwrap(), None);
+        let event = scheduler.next().unwrap();
+        assert_eq!(
+            event,
+            json!({
+                CONTINUATION: {
+                    "Request": request(),
+                    "State": 1,
+                },
+            })
+        );
+
+        assert_eq!(processor(event).wait().unwrap(), None);
+        let event = scheduler.next().unwrap();
+        assert_eq!(event[CONTINUATION]["State"], 2);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn malformed_continuation() {
+        let processor = process_continuable(
+            LocalScheduler::new(),
+            |_request: CfnRequest<Ignored>, _state: Option<u32>| Ok(Progress::Done(None::<()>)),
+        );
+        assert!(processor(json!({ CONTINUATION: { "State": 1 } }))
+            .wait()
+            .is_err());
+    }
+}
@@ -0,0 +1,344 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use futures::{Future, IntoFuture};
+use {CfnRequest, Provider, ResponseFields, SensitiveProperties};
+
+/// Resource properties of which some cannot be changed after the resource was created.
+///
+/// Implementors compare the create-only properties of `self`, the new properties, with the old
+/// ones, reporting every property that differs to the given [`CreateOnlyChanges`].
+/// [`enforce_create_only`] uses this to reject or replace Updates that change such properties.
+///
+/// With the `derive` feature enabled, `#[custom_resource]` implements this trait for you, treating
+/// fields marked with `#[create_only]` as create-only.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # use cfn_resource_provider::*;
+/// struct MyResourceProperties {
+///     bucket_name: String,
+///     tags: Vec<String>,
+/// }
+///
+/// impl CreateOnlyProperties for MyResourceProperties {
+///     fn compare_create_only(&self, old: &Self, changes: &mut CreateOnlyChanges) {
+///         changes.compare("BucketName", &self.bucket_name, &old.bucket_name);
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// [`CreateOnlyChanges`]: struct.CreateOnlyChanges.html
+/// [`enforce_create_only`]: fn.enforce_create_only.html
+pub trait CreateOnlyProperties {
+    /// Reports every create-only property that differs between `self` and `old` to `changes`.
+    fn compare_create_only(&self, old: &Self, changes: &mut CreateOnlyChanges);
+}
+
+/// The create-only properties changed by an Update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CreateOnlyChanges {
+    properties: Vec<String>,
+}
+
+impl CreateOnlyChanges {
+    /// Records the given property as changed if its new and old values differ.
+    pub fn compare<T>(&mut self, property: &str, new: &T, old: &T)
+    where
+        T: PartialEq + ?Sized,
+    {
+        if new != old {
+            self.properties.push(property.to_owned());
+        }
+    }
+
+    /// The names of the changed properties.
+    pub fn properties(&self) -> &[String] {
+        &self.properties
+    }
+
+    /// Whether no create-only property was changed.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+}
+
+/// What to do with an Update that changes create-only properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreateOnlyPolicy {
+    /// Reject the Update, informing AWS CloudFormation which properties cannot be changed. The
+    /// existing resource is left untouched.
+    Reject,
+    /// Replace the resource: the Update is turned into a Create request for a new resource.
+    ///
+    /// AWS CloudFormation only replaces the resource if the physical resource ID of the new
+    /// resource differs from the existing one, in which case it deletes the existing resource
+    /// afterwards. Thus the physical resource ID generated on Create must change with the
+    /// create-only properties, e.g. by making them part of the
+    /// [`PhysicalResourceIdSuffixProvider`] suffix, or by using the [`RandomUuidStrategy`].
+    ///
+    /// [`PhysicalResourceIdSuffixProvider`]: trait.PhysicalResourceIdSuffixProvider.html
+    /// [`RandomUuidStrategy`]: struct.RandomUuidStrategy.html
+    Replace,
+}
+
+impl<P> CfnRequest<P>
+where
+    P: CreateOnlyProperties,
+{
+    /// The create-only properties changed by the request, which can only be non-empty for Update
+    /// requests.
+    pub fn create_only_changes(&self) -> CreateOnlyChanges {
+        let mut changes = CreateOnlyChanges::default();
+        if let CfnRequest::Update {
+            ref resource_properties,
+            ref old_resource_properties,
+            ..
+        } = *self
+        {
+            resource_properties.compare_create_only(old_resource_properties, &mut changes);
+        }
+        changes
+    }
+}
+
+/// Enforce the create-only properties of Update requests according to the given policy.
+///
+/// Requests that do not change create-only properties are passed to `f` unaltered. `f` is expected
+/// to inform AWS CloudFormation itself, which makes it usually the function returned by [`process`]
+/// or one of its siblings. Updates that do change create-only properties are handled according to
+/// the [`CreateOnlyPolicy`]:
+///
+/// * [`Reject`] informs AWS CloudFormation about the failure without invoking `f`, keeping the
+///   existing physical resource ID.
+/// * [`Replace`] passes a Create request to `f` instead.
+///
+/// Use [`Provider::enforce_create_only`] to configure how rejected updates are responded to.
+///
+/// [`process`]: fn.process.html
+/// [`CreateOnlyPolicy`]: enum.CreateOnlyPolicy.html
+/// [`Reject`]: enum.CreateOnlyPolicy.html#variant.Reject
+/// [`Replace`]: enum.CreateOnlyPolicy.html#variant.Replace
+/// [`Provider::enforce_create_only`]: struct.Provider.html#method.enforce_create_only
+pub fn enforce_create_only<F, R, P, S>(
+    policy: CreateOnlyPolicy,
+    f: F,
+) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + 'static,
+    R::Future: Send,
+    P: CreateOnlyProperties + SensitiveProperties,
+    S: Send + 'static,
+{
+    Provider::new().enforce_create_only(policy, f)
+}
+
+impl<T> Provider<T> {
+    /// Enforce the create-only properties of Update requests according to the given policy with
+    /// this configuration, as [`enforce_create_only`] would.
+    ///
+    /// The configuration applies to the failure responses sent for rejected updates, `f` is
+    /// expected to be configured on its own.
+    ///
+    /// [`enforce_create_only`]: fn.enforce_create_only.html
+    pub fn enforce_create_only<F, R, P, S>(
+        self,
+        policy: CreateOnlyPolicy,
+        f: F,
+    ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + 'static,
+        R::Future: Send,
+        P: CreateOnlyProperties + SensitiveProperties,
+        S: Send + 'static,
+    {
+        let (_, responder) = self.into_parts();
+        move |request: CfnRequest<P>| {
+            let changes = request.create_only_changes();
+            if changes.is_empty() {
+                return Box::new(f(request).into_future());
+            }
+
+            match policy {
+                CreateOnlyPolicy::Reject => {
+                    let response_url = request.response_url().to_owned();
+                    let measured = responder.measure(&request);
+                    let sensitive_values = request.sensitive_values();
+                    let physical_resource_id = match request {
+                        CfnRequest::Update {
+                            ref physical_resource_id,
+                            ..
+                        } => physical_resource_id.clone(),
+                        _ => unreachable!("only Update requests can change create-only properties"),
+                    };
+                    let response_fields = ResponseFields::new(&request, physical_resource_id);
+                    let result = Err(format_err!(
+                        "the create-only {} {} cannot be updated, the resource has to be replaced \
+                         instead",
+                        if changes.properties().len() == 1 {
+                            "property"
+                        } else {
+                            "properties"
+                        },
+                        changes
+                            .properties()
+                            .iter()
+                            .map(|property| format!("`{}`", property))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    ));
+                    let cfn_response =
+                        responder.response::<()>(response_fields, &result, &sensitive_values);
+                    Box::new(
+                        responder
+                            .send(response_url, &cfn_response, measured)
+                            .and_then(move |_| result.map(|_| None)),
+                    )
+                }
+                CreateOnlyPolicy::Replace => Box::new(f(request.into_create()).into_future()),
+            }
+        }
+    }
+}
+
+impl<P> CfnRequest<P> {
+    /// Turns the request into a Create request for the same resource properties.
+    fn into_create(self) -> CfnRequest<P> {
+        match self {
+            CfnRequest::Update {
+                request_id,
+                response_url,
+                resource_type,
+                logical_resource_id,
+                stack_id,
+                resource_properties,
+                ..
+            }
+            | CfnRequest::Delete {
+                request_id,
+                response_url,
+                resource_type,
+                logical_resource_id,
+                stack_id,
+                resource_properties,
+                ..
+            }
+            | CfnRequest::Create {
+                request_id,
+                response_url,
+                resource_type,
+                logical_resource_id,
+                stack_id,
+                resource_properties,
+            } => CfnRequest::Create {
+                request_id,
+                response_url,
+                resource_type,
+                logical_resource_id,
+                stack_id,
+                resource_properties,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::future;
+    use SensitiveValues;
+
+    #[derive(Debug, PartialEq)]
+    struct Properties {
+        name: String,
+        zone: String,
+        size: u32,
+    }
+
+    impl CreateOnlyProperties for Properties {
+        fn compare_create_only(&self, old: &Self, changes: &mut CreateOnlyChanges) {
+            changes.compare("Name", &self.name, &old.name);
+            changes.compare("Zone", &self.zone, &old.zone);
+        }
+    }
+
+    impl SensitiveProperties for Properties {
+        fn sensitive_values(&self, _values: &mut SensitiveValues) {}
+    }
+
+    fn properties(name: &str, zone: &str, size: u32) -> Properties {
+        Properties {
+            name: name.to_owned(),
+            zone: zone.to_owned(),
+            size,
+        }
+    }
+
+    fn update(new: Properties, old: Properties) -> CfnRequest<Properties> {
+        CfnRequest::Update {
+            request_id: "REQUEST-ID".to_owned(),
+            response_url: "http://localhost/presigned-url".to_owned(),
+            resource_type: "Custom::Thing".to_owned(),
+            logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+            stack_id: "STACK-ID".to_owned(),
+            physical_resource_id: "PHYSICAL-RESOURCE-ID".to_owned(),
+            resource_properties: new,
+            old_resource_properties: old,
+        }
+    }
+
+    #[test]
+    fn changes() {
+        let request = update(properties("a", "x", 1), properties("a", "x", 2));
+        assert!(request.create_only_changes().is_empty());
+
+        let request = update(properties("b", "y", 1), properties("a", "x", 1));
+        assert_eq!(
+            request.create_only_changes().properties(),
+            &["Name", "Zone"]
+        );
+    }
+
+    #[test]
+    fn unchanged_update_is_passed_on() {
+        let processor = enforce_create_only(CreateOnlyPolicy::Reject, |request| {
+            future::ok::<_, Error>(Some(request))
+        });
+        let request = update(properties("a", "x", 1), properties("a", "x", 2));
+        let passed = processor(request).wait().unwrap().unwrap();
+        match passed {
+            CfnRequest::Update { .. } => {}
+            request => panic!("unexpected request: {:?}", request),
+        }
+    }
+
+    #[test]
+    fn replace_turns_update_into_create() {
+        let processor = enforce_create_only(CreateOnlyPolicy::Replace, |request| {
+            future::ok::<_, Error>(Some(request))
+        });
+        let request = update(properties("b", "x", 1), properties("a", "x", 1));
+        assert_eq!(
+            processor(request).wait().unwrap().unwrap(),
+            CfnRequest::Create {
+                request_id: "REQUEST-ID".to_owned(),
+                response_url: "http://localhost/presigned-url".to_owned(),
+                resource_type: "Custom::Thing".to_owned(),
+                logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+                stack_id: "STACK-ID".to_owned(),
+                resource_properties: properties("b", "x", 1),
+            }
+        );
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use serde::de::{self, DeserializeOwned};
+use serde::ser::Serialize;
+use serde_json::{Map, Value};
+use {
+    from_value_coerced, CfnRequest, PhysicalIdStrategy, PhysicalResourceIdSuffixProvider, Provider,
+    RequestType, SensitiveProperties,
+};
+
+/// A resource property type that belongs to exactly one custom resource type, e.g.
+/// `Custom::MyCustomResourceType`.
+///
+/// Implementing this trait allows the resource type of a request to be verified through
+/// [`CfnRequest::check_resource_type`], which [`process_custom_resource`] does before invoking
+/// your handler.
+///
+/// With the `derive` feature enabled, the `#[custom_resource(type = "...")]` attribute implements
+/// this trait for you, along with deserialization and [`PhysicalResourceIdSuffixProvider`].
+///
+/// [`CfnRequest::check_resource_type`]: enum.CfnRequest.html#method.check_resource_type
+/// [`process_custom_resource`]: fn.process_custom_resource.html
+/// [`PhysicalResourceIdSuffixProvider`]: trait.PhysicalResourceIdSuffixProvider.html
+pub trait CustomResource {
+    /// The resource type of the custom resource, as specified in the AWS CloudFormation template.
+    const RESOURCE_TYPE: &'static str;
+}
+
+impl<P> CfnRequest<P>
+where
+    P: CustomResource,
+{
+    /// Verifies that the resource type of the request matches the resource type the resource
+    /// properties belong to, as declared through [`CustomResource`].
+    ///
+    /// Delete requests are never rejected, as failing them leaves the stack stuck, e.g. after the
+    /// resource type was renamed or the `ServiceToken` of a resource was changed.
+    ///
+    /// [`CustomResource`]: trait.CustomResource.html
+    pub fn check_resource_type(&self) -> Result<(), Error> {
+        if self.request_type() == RequestType::Delete || self.resource_type() == P::RESOURCE_TYPE {
+            Ok(())
+        } else {
+            Err(format_err!(
+                "unexpected resource type `{}`, expected `{}`",
+                self.resource_type(),
+                P::RESOURCE_TYPE,
+            ))
+        }
+    }
+}
+
+/// Process an AWS CloudFormation custom resource request for a [`CustomResource`].
+///
+/// This behaves like [`process`], but verifies the resource type of the request before invoking
+/// your closure. If the resource type of a Create or Update request does not match, your closure
+/// is not invoked and AWS CloudFormation is informed about the failure. Delete requests are passed
+/// to your closure regardless of their resource type.
+///
+/// The values of the [`SensitiveProperties`] are redacted from the failure reasons, as
+/// [`Provider::process_sensitive`] does.
+///
+/// Use [`Provider::process_custom_resource`] to configure how the requests are processed.
+///
+/// [`CustomResource`]: trait.CustomResource.html
+/// [`process`]: fn.process.html
+/// [`SensitiveProperties`]: trait.SensitiveProperties.html
+/// [`Provider::process_sensitive`]: struct.Provider.html#method.process_sensitive
+/// [`Provider::process_custom_resource`]: struct.Provider.html#method.process_custom_resource
+pub fn process_custom_resource<F, R, P, S>(
+    f: F,
+) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+    R::Future: Send,
+    S: Serialize + Send + 'static,
+    P: CustomResource + PhysicalResourceIdSuffixProvider + SensitiveProperties + Send + 'static,
+{
+    Provider::new().process_custom_resource(f)
+}
+
+impl<T> Provider<T> {
+    /// Process AWS CloudFormation custom resource requests for a [`CustomResource`] with this
+    /// configuration, as [`process_custom_resource`] would.
+    ///
+    /// [`CustomResource`]: trait.CustomResource.html
+    /// [`process_custom_resource`]: fn.process_custom_resource.html
+    pub fn process_custom_resource<F, R, P, S>(
+        self,
+        f: F,
+    ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+        R::Future: Send,
+        S: Serialize + Send + 'static,
+        P: CustomResource + SensitiveProperties + Send + 'static,
+    {
+        self.process_sensitive(
+            move |request: CfnRequest<P>| match request.check_resource_type() {
+                Ok(()) => Either::A(f(request).into_future()),
+                Err(e) => Either::B(future::err(e)),
+            },
+        )
+    }
+}
+
+/// Support for the code generated by `#[custom_resource]`, not meant to be used directly.
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    pub use serde::de::{Deserialize, Deserializer};
+
+    /// The resource properties of a request, from which the generated deserialization retrieves
+    /// the properties one by one, collecting all errors along the way.
+    pub struct Properties {
+        properties: Map<String, Value>,
+        errors: Vec<String>,
+    }
+
+    impl Properties {
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Properties, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let properties = match Value::deserialize(deserializer)? {
+                Value::Object(properties) => properties,
+                Value::Null => Map::new(),
+                value => {
+                    return Err(de::Error::custom(format!(
+                        "expected the resource properties to be an object, found `{}`",
+                        value
+                    )))
+                }
+            };
+            Ok(Properties {
+                properties,
+                errors: Vec::new(),
+            })
+        }
+
+        pub fn required<T>(&mut self, name: &str) -> Option<T>
+        where
+            T: DeserializeOwned,
+        {
+            match self.properties.remove(name) {
+                None | Some(Value::Null) => {
+                    self.errors
+                        .push(format!("missing required property `{}`", name));
+                    None
+                }
+                Some(value) => self.convert(name, value),
+            }
+        }
+
+        pub fn optional<T>(&mut self, name: &str) -> Option<T>
+        where
+            T: DeserializeOwned,
+        {
+            match self.properties.remove(name) {
+                None | Some(Value::Null) => None,
+                Some(value) => self.convert(name, value),
+            }
+        }
+
+        fn convert<T>(&mut self, name: &str, value: Value) -> Option<T>
+        where
+            T: DeserializeOwned,
+        {
+            match from_value_coerced(value) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    self.errors
+                        .push(format!("invalid property `{}`: {}", name, e));
+                    None
+                }
+            }
+        }
+
+        pub fn finish<E>(self) -> Result<(), E>
+        where
+            E: de::Error,
+        {
+            if self.errors.is_empty() {
+                Ok(())
+            } else {
+                Err(E::custom(self.errors.join(", ")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::__private::Properties;
+    use super::*;
+
+    struct Thing;
+    impl CustomResource for Thing {
+        const RESOURCE_TYPE: &'static str = "Custom::Thing";
+    }
+
+    fn request(resource_type: &str) -> CfnRequest<Thing> {
+        CfnRequest::Create {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: resource_type.to_owned(),
+            logical_resource_id: String::new(),
+            stack_id: String::new(),
+            resource_properties: Thing,
+        }
+    }
+
+    #[test]
+    fn check_resource_type() {
+        assert!(request("Custom::Thing").check_resource_type().is_ok());
+        assert!(request("Custom::Other").check_resource_type().is_err());
+    }
+
+    #[test]
+    fn delete_requests_are_not_checked() {
+        let request = CfnRequest::Delete {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: "Custom::Other".to_owned(),
+            logical_resource_id: String::new(),
+            stack_id: String::new(),
+            physical_resource_id: String::new(),
+            resource_properties: Thing,
+        };
+        assert!(request.check_resource_type().is_ok());
+    }
+
+    #[test]
+    fn properties_collect_all_errors() {
+        let mut properties = Properties::deserialize(json!({
+            "Size": "five",
+            "Name": "name",
+        }))
+        .unwrap();
+        assert_eq!(
+            properties.required::<String>("Name"),
+            Some("name".to_owned())
+        );
+        assert_eq!(properties.required::<u32>("Size"), None);
+        assert_eq!(properties.required::<String>("Missing"), None);
+        assert_eq!(properties.optional::<String>("Optional"), None);
+        let error = properties.finish::<serde_json::Error>().unwrap_err();
+        assert!(error.to_string().contains("invalid property `Size`"));
+        assert!(error
+            .to_string()
+            .contains("missing required property `Missing`"));
+        assert!(!error.to_string().contains("Optional"));
+    }
+
+    #[test]
+    fn properties_must_be_object() {
+        assert!(Properties::deserialize(json!("string")).is_err());
+        assert!(Properties::deserialize(json!(null))
+            .unwrap()
+            .finish::<serde_json::Error>()
+            .is_ok());
+    }
+}
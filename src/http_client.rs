@@ -0,0 +1,210 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use reqwest::async::{Client, ClientBuilder};
+use reqwest::Proxy;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// The HTTP client responses are sent to AWS CloudFormation with.
+///
+/// The underlying `reqwest` client is built on the first response sent and reused for all later
+/// ones, such that warm AWS Lambda invocations neither pay for setting up TLS and the connection
+/// pool again, nor for establishing a new connection if the previous one is still alive. Clones
+/// share the client.
+///
+/// Use [`Provider::http_client`] to configure the client, e.g. to set timeouts or a proxy.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # extern crate reqwest;
+/// # use cfn_resource_provider::*;
+/// # use std::time::Duration;
+/// # fn main() {
+/// let http_client = HttpClient::new()
+///     .timeout(Duration::from_secs(10))
+///     .proxy(reqwest::Proxy::https("http://proxy.internal:3128").unwrap());
+/// let processor = Provider::new()
+///     .http_client(http_client)
+///     .process(|event: CfnRequest<Ignored>| Ok(None::<()>));
+/// # }
+/// ```
+///
+/// [`Provider::http_client`]: struct.Provider.html#method.http_client
+#[derive(Clone, Default)]
+pub struct HttpClient {
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxies: Vec<Proxy>,
+    configure: Option<Arc<dyn Fn(ClientBuilder) -> ClientBuilder + Send + Sync>>,
+    client: Arc<Mutex<Option<Client>>>,
+}
+
+impl HttpClient {
+    /// Creates a client with the defaults of `reqwest`, which does not time out and uses the
+    /// proxies configured in the environment.
+    pub fn new() -> HttpClient {
+        HttpClient::default()
+    }
+
+    /// Times out sending a response after the given duration, from connecting until the response
+    /// of Amazon S3 has been read.
+    pub fn timeout(mut self, timeout: Duration) -> HttpClient {
+        self.timeout = Some(timeout);
+        self.unbuilt()
+    }
+
+    /// Times out connecting to Amazon S3 after the given duration.
+    pub fn connect_timeout(mut self, timeout: Duration) -> HttpClient {
+        self.connect_timeout = Some(timeout);
+        self.unbuilt()
+    }
+
+    /// Sends responses through the given proxy. If multiple proxies are added, the first one
+    /// matching the response URL is used.
+    pub fn proxy(mut self, proxy: Proxy) -> HttpClient {
+        self.proxies.push(proxy);
+        self.unbuilt()
+    }
+
+    /// Applies any further configuration to the `reqwest` client builder, after the options above.
+    ///
+    /// This is how a different TLS backend is chosen, e.g. `use_rustls_tls` after enabling the
+    /// `rustls-tls` feature of `reqwest`, or additional root certificates are added.
+    pub fn configure<C>(mut self, configure: C) -> HttpClient
+    where
+        C: Fn(ClientBuilder) -> ClientBuilder + Send + Sync + 'static,
+    {
+        self.configure = Some(Arc::new(configure));
+        self.unbuilt()
+    }
+
+    /// Returns the shared client, building it first if this is the first response sent.
+    ///
+    /// A `configure` closure panicking while building the client does not prevent later attempts.
+    pub(crate) fn client(&self) -> Result<Client, Error> {
+        let mut client = self.client.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(ref client) = *client {
+            return Ok(client.clone());
+        }
+        let built = self.builder().build()?;
+        *client = Some(built.clone());
+        Ok(built)
+    }
+
+    fn builder(&self) -> ClientBuilder {
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        for proxy in &self.proxies {
+            builder = builder.proxy(proxy.clone());
+        }
+        match self.configure {
+            Some(ref configure) => configure(builder),
+            None => builder,
+        }
+    }
+
+    /// Detaches from any client built with the previous configuration, which clones might share.
+    fn unbuilt(mut self) -> HttpClient {
+        self.client = Arc::default();
+        self
+    }
+}
+
+impl fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpClient")
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("proxies", &self.proxies)
+            .field("configured", &self.configure.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_built(http_client: &HttpClient) -> bool {
+        http_client
+            .client
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    #[test]
+    fn client_is_built_once_and_shared() {
+        let http_client = HttpClient::new().timeout(Duration::from_secs(1));
+        let clone = http_client.clone();
+        assert!(!is_built(&clone));
+
+        http_client.client().unwrap();
+        assert!(is_built(&clone));
+        assert!(Arc::ptr_eq(&http_client.client, &clone.client));
+    }
+
+    #[test]
+    fn reconfiguring_detaches_from_the_built_client() {
+        let http_client = HttpClient::new();
+        http_client.client().unwrap();
+
+        let reconfigured = http_client.clone().connect_timeout(Duration::from_secs(1));
+        assert!(!is_built(&reconfigured));
+        assert!(is_built(&http_client));
+    }
+
+    #[test]
+    fn configure_is_applied() {
+        let configured = Arc::new(Mutex::new(false));
+        let http_client = {
+            let configured = configured.clone();
+            HttpClient::new().configure(move |builder| {
+                *configured.lock().unwrap() = true;
+                builder
+            })
+        };
+        http_client.client().unwrap();
+        http_client.client().unwrap();
+        assert!(*configured.lock().unwrap());
+    }
+
+    #[test]
+    fn panicking_configure_does_not_poison_the_client() {
+        let panicked = Arc::new(Mutex::new(false));
+        let http_client = {
+            let panicked = panicked.clone();
+            HttpClient::new().configure(move |builder| {
+                let mut panicked = panicked.lock().unwrap();
+                if !*panicked {
+                    *panicked = true;
+                    drop(panicked);
+                    panic!("configure panicked");
+                }
+                builder
+            })
+        };
+        let panicking = http_client.clone();
+        assert!(::std::thread::spawn(move || panicking.client())
+            .join()
+            .is_err());
+
+        http_client.client().unwrap();
+        assert!(is_built(&http_client));
+    }
+}
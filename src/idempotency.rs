@@ -0,0 +1,679 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::{Error, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use serde::ser::Serialize;
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tracing_futures::Instrument;
+use uuid::Uuid;
+use {
+    log_request, request_span, CfnRequest, CfnResponse, PhysicalIdStrategy,
+    PhysicalResourceIdSuffixProvider, Provider, ResponseFields, SensitiveProperties,
+};
+
+/// The default number of requests a [`MemoryStore`] retains.
+///
+/// [`MemoryStore`]: struct.MemoryStore.html
+const DEFAULT_MEMORY_STORE_CAPACITY: usize = 1000;
+
+/// The default time after which a claim is considered abandoned, the longest an AWS Lambda
+/// invocation can run.
+const DEFAULT_CLAIM_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// A store recording the response sent for every request, keyed by the ID of the request.
+///
+/// AWS CloudFormation and AWS Lambda can deliver the same request more than once, e.g. when
+/// retrying or when delivering through Amazon SNS. [`process_idempotent`] uses the store to send
+/// the recorded response for requests that were already processed, instead of processing them
+/// again. Before processing a request, it [`claim`]s the request ID, such that a duplicate
+/// delivered while the first one is still being processed is not processed concurrently.
+///
+/// Claims expire: an invocation can end without recording a response, e.g. because it timed out,
+/// and the request has to be processed again once it is delivered again. Both stores provided
+/// consider claims older than 15 minutes abandoned by default, which is configurable through
+/// their `claim_timeout` methods.
+///
+/// This crate provides the [`MemoryStore`] and the [`FileStore`]. Implement this trait to record
+/// the responses in a persistent backend, e.g. an Amazon DynamoDB table, using a conditional write
+/// to claim request IDs.
+///
+/// [`process_idempotent`]: fn.process_idempotent.html
+/// [`claim`]: #tymethod.claim
+/// [`MemoryStore`]: struct.MemoryStore.html
+/// [`FileStore`]: struct.FileStore.html
+pub trait IdempotencyStore: Send + Sync {
+    /// Retrieves the response recorded for the given request ID, if any.
+    fn get(
+        &self,
+        request_id: &str,
+    ) -> Box<dyn Future<Item = Option<CfnResponse>, Error = Error> + Send>;
+
+    /// Claims the given request ID for processing, unless it is claimed already or a response is
+    /// recorded for it. A claim that has expired is claimed again.
+    ///
+    /// Checking and claiming has to happen atomically, otherwise two concurrent deliveries of the
+    /// same request could both claim it. The claim is released by [`put`].
+    ///
+    /// [`put`]: #tymethod.put
+    fn claim(&self, request_id: &str) -> Box<dyn Future<Item = Claim, Error = Error> + Send>;
+
+    /// Records the response for the given request ID, replacing any previously recorded response
+    /// or claim.
+    fn put(
+        &self,
+        request_id: &str,
+        response: &CfnResponse,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+}
+
+/// The outcome of [`IdempotencyStore::claim`].
+///
+/// [`IdempotencyStore::claim`]: trait.IdempotencyStore.html#tymethod.claim
+#[derive(Debug, Clone, PartialEq)]
+pub enum Claim {
+    /// The request ID was claimed, the request is to be processed.
+    Claimed,
+    /// The request ID was claimed before and the claim has not expired yet, the request is still
+    /// being processed.
+    InProgress,
+    /// The request was processed before, resulting in the recorded response.
+    Recorded(CfnResponse),
+}
+
+impl<T> IdempotencyStore for Arc<T>
+where
+    T: IdempotencyStore + ?Sized,
+{
+    fn get(
+        &self,
+        request_id: &str,
+    ) -> Box<dyn Future<Item = Option<CfnResponse>, Error = Error> + Send> {
+        (**self).get(request_id)
+    }
+
+    fn claim(&self, request_id: &str) -> Box<dyn Future<Item = Claim, Error = Error> + Send> {
+        (**self).claim(request_id)
+    }
+
+    fn put(
+        &self,
+        request_id: &str,
+        response: &CfnResponse,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        (**self).put(request_id, response)
+    }
+}
+
+/// An [`IdempotencyStore`] keeping the responses in memory.
+///
+/// The responses are only retained as long as the store lives, which in AWS Lambda is at most the
+/// lifetime of the execution environment. Requests delivered to a different execution environment
+/// are not recognized.
+///
+/// To bound the memory used in long-lived execution environments, the store retains at most a
+/// fixed number of requests, 1000 by default, forgetting the least recently claimed ones first.
+///
+/// Claims expire after 15 minutes by default, see [`claim_timeout`].
+///
+/// [`IdempotencyStore`]: trait.IdempotencyStore.html
+/// [`claim_timeout`]: #method.claim_timeout
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    claim_timeout: Duration,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryEntries {
+    entries: HashMap<String, MemoryEntry>,
+    /// The request IDs in the order they were added, oldest first.
+    order: VecDeque<String>,
+}
+
+#[derive(Debug)]
+enum MemoryEntry {
+    /// The request was claimed at the given time, without a response recorded yet.
+    Claimed(Instant),
+    Recorded(CfnResponse),
+}
+
+impl MemoryEntries {
+    fn insert(&mut self, request_id: &str, entry: MemoryEntry, capacity: usize) {
+        if self.entries.insert(request_id.to_owned(), entry).is_none() {
+            self.order.push_back(request_id.to_owned());
+        }
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Creates an empty store, retaining up to 1000 requests.
+    pub fn new() -> MemoryStore {
+        MemoryStore::with_capacity(DEFAULT_MEMORY_STORE_CAPACITY)
+    }
+
+    /// Creates an empty store, retaining up to the given number of requests.
+    pub fn with_capacity(capacity: usize) -> MemoryStore {
+        MemoryStore {
+            capacity,
+            claim_timeout: DEFAULT_CLAIM_TIMEOUT,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Sets the time after which a claim without a recorded response is considered abandoned,
+    /// allowing the request to be claimed again. This defaults to 15 minutes.
+    pub fn claim_timeout(mut self, claim_timeout: Duration) -> MemoryStore {
+        self.claim_timeout = claim_timeout;
+        self
+    }
+
+    fn entries<'a>(&'a self) -> MutexGuard<'a, MemoryEntries> {
+        // Neither operation can leave the entries inconsistent, thus a poisoned lock is harmless.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        MemoryStore::new()
+    }
+}
+
+impl IdempotencyStore for MemoryStore {
+    fn get(
+        &self,
+        request_id: &str,
+    ) -> Box<dyn Future<Item = Option<CfnResponse>, Error = Error> + Send> {
+        let response = match self.entries().entries.get(request_id) {
+            Some(MemoryEntry::Recorded(response)) => Some(response.clone()),
+            _ => None,
+        };
+        Box::new(future::ok(response))
+    }
+
+    fn claim(&self, request_id: &str) -> Box<dyn Future<Item = Claim, Error = Error> + Send> {
+        let mut entries = self.entries();
+        let claim = match entries.entries.get(request_id) {
+            Some(MemoryEntry::Recorded(response)) => Claim::Recorded(response.clone()),
+            Some(MemoryEntry::Claimed(claimed)) if claimed.elapsed() < self.claim_timeout => {
+                Claim::InProgress
+            }
+            _ => {
+                entries.insert(
+                    request_id,
+                    MemoryEntry::Claimed(Instant::now()),
+                    self.capacity,
+                );
+                Claim::Claimed
+            }
+        };
+        Box::new(future::ok(claim))
+    }
+
+    fn put(
+        &self,
+        request_id: &str,
+        response: &CfnResponse,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        self.entries().insert(
+            request_id,
+            MemoryEntry::Recorded(response.clone()),
+            self.capacity,
+        );
+        Box::new(future::ok(()))
+    }
+}
+
+/// An [`IdempotencyStore`] keeping every response as a JSON file in a directory.
+///
+/// In AWS Lambda only `/tmp` is writable, which is not shared between execution environments. Use
+/// a directory on a mounted Amazon EFS file system to share the responses between them.
+///
+/// Request IDs are claimed by exclusively creating a claim file next to the response. Should an
+/// invocation end without recording a response, e.g. because it timed out, its claim file remains
+/// until it is older than the [`claim_timeout`], 15 minutes by default, after which the request is
+/// claimed again by replacing the file. The responses are never removed by the store.
+///
+/// [`IdempotencyStore`]: trait.IdempotencyStore.html
+/// [`claim_timeout`]: #method.claim_timeout
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    directory: PathBuf,
+    claim_timeout: Duration,
+}
+
+impl FileStore {
+    /// Creates a store keeping the responses in the given directory, which is created if it does
+    /// not exist.
+    pub fn new<D>(directory: D) -> Result<FileStore, Error>
+    where
+        D: Into<PathBuf>,
+    {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).with_context(|_| {
+            format!(
+                "failed to create the directory `{}`",
+                directory.to_string_lossy()
+            )
+        })?;
+        Ok(FileStore {
+            directory,
+            claim_timeout: DEFAULT_CLAIM_TIMEOUT,
+        })
+    }
+
+    /// Sets the time after which a claim file without a recorded response is considered
+    /// abandoned, allowing the request to be claimed again. This defaults to 15 minutes.
+    ///
+    /// The age of a claim file is determined by its modification time, thus the clocks of all
+    /// execution environments sharing the directory have to be in sync.
+    pub fn claim_timeout(mut self, claim_timeout: Duration) -> FileStore {
+        self.claim_timeout = claim_timeout;
+        self
+    }
+
+    /// The file the response for the given request ID is kept in. Request IDs are hashed, as they
+    /// are not guaranteed to be valid file names.
+    fn path(&self, request_id: &str) -> PathBuf {
+        let hash = Sha256::digest(request_id.as_bytes());
+        let name: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.directory.join(format!("{}.json", name))
+    }
+
+    fn read(&self, request_id: &str) -> Result<Option<CfnResponse>, Error> {
+        match fs::read(self.path(request_id)) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map(Some)
+                .map_err(Into::into),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn try_claim(&self, request_id: &str) -> Result<Claim, Error> {
+        if let Some(response) = self.read(request_id)? {
+            return Ok(Claim::Recorded(response));
+        }
+        let claim = self.path(request_id).with_extension("claim");
+        match create_claim(&claim) {
+            Ok(()) => Ok(Claim::Claimed),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                // The response might have been recorded since it was read above.
+                if let Some(response) = self.read(request_id)? {
+                    return Ok(Claim::Recorded(response));
+                }
+                if !self.is_expired(&claim)? {
+                    return Ok(Claim::InProgress);
+                }
+                // Moving the expired claim out of the way first ensures that of multiple
+                // concurrent deliveries only the one that moved it can claim the request.
+                let expired =
+                    claim.with_extension(format!("{}.expired", Uuid::new_v4().to_simple()));
+                match fs::rename(&claim, &expired) {
+                    Ok(()) => {}
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                        return Ok(Claim::InProgress)
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+                fs::remove_file(&expired)?;
+                warn!("the claim of the request has expired, claiming it again");
+                match create_claim(&claim) {
+                    Ok(()) => Ok(Claim::Claimed),
+                    Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(Claim::InProgress),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the claim file was last modified longer than the claim timeout ago. A claim file
+    /// removed in the meantime is not expired, as its request has been recorded.
+    fn is_expired(&self, claim: &Path) -> Result<bool, Error> {
+        let modified = match fs::metadata(claim).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        // A modification time in the future, i.e. clock skew, is treated as a fresh claim.
+        Ok(SystemTime::now()
+            .duration_since(modified)
+            .map(|age| age >= self.claim_timeout)
+            .unwrap_or(false))
+    }
+}
+
+/// Exclusively creates the claim file, failing with `AlreadyExists` if it exists.
+fn create_claim(claim: &Path) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(claim)
+        .map(|_| ())
+}
+
+impl IdempotencyStore for FileStore {
+    fn get(
+        &self,
+        request_id: &str,
+    ) -> Box<dyn Future<Item = Option<CfnResponse>, Error = Error> + Send> {
+        Box::new(self.read(request_id).into_future())
+    }
+
+    fn claim(&self, request_id: &str) -> Box<dyn Future<Item = Claim, Error = Error> + Send> {
+        Box::new(self.try_claim(request_id).into_future())
+    }
+
+    fn put(
+        &self,
+        request_id: &str,
+        response: &CfnResponse,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        let path = self.path(request_id);
+        // Writing to a temporary file first ensures that concurrent readers never observe a
+        // partially written response.
+        let temporary = path.with_extension(format!("{}.tmp", Uuid::new_v4().to_simple()));
+        let result = serde_json::to_vec(response)
+            .map_err(Error::from)
+            .and_then(|content| fs::write(&temporary, content).map_err(Into::into))
+            .and_then(|_| fs::rename(&temporary, &path).map_err(Into::into))
+            .and_then(|_| match fs::remove_file(path.with_extension("claim")) {
+                Err(ref e) if e.kind() != io::ErrorKind::NotFound => Err(format_err!(
+                    "failed to release the claim of request `{}`: {}",
+                    request_id,
+                    e
+                )),
+                _ => Ok(()),
+            });
+        Box::new(result.into_future())
+    }
+}
+
+/// Process an AWS CloudFormation custom resource request at most once, recording the response in
+/// the given [`IdempotencyStore`].
+///
+/// This behaves like [`process`], but claims the request ID in the store before invoking your
+/// closure and records the response sent to AWS CloudFormation in the store before sending it.
+/// Should recording the response fail, it is sent nonetheless and the failure is logged. If
+/// a request with the same request ID is processed again, the recorded response is sent instead of
+/// invoking your closure. In that case the result returned to the caller is `Ok(None)` for a
+/// recorded success, as the data cannot be deserialized back into `S`, and an error stating the
+/// recorded reason for a recorded failure. A request delivered again while it is still being
+/// processed is ignored, returning `Ok(None)` without sending a response, unless the claim has
+/// expired, in which case the request is processed again.
+///
+/// The values of the [`SensitiveProperties`] are redacted from the failure reasons, as
+/// [`Provider::process_sensitive`] does.
+///
+/// Use [`Provider::process_idempotent`] to configure how the requests are processed.
+///
+/// [`IdempotencyStore`]: trait.IdempotencyStore.html
+/// [`process`]: fn.process.html
+/// [`SensitiveProperties`]: trait.SensitiveProperties.html
+/// [`Provider::process_sensitive`]: struct.Provider.html#method.process_sensitive
+/// [`Provider::process_idempotent`]: struct.Provider.html#method.process_idempotent
+pub fn process_idempotent<I, F, R, P, S>(
+    store: I,
+    f: F,
+) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    I: IdempotencyStore + 'static,
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+    R::Future: Send,
+    S: Serialize + Send + 'static,
+    P: PhysicalResourceIdSuffixProvider + SensitiveProperties + Send + 'static,
+{
+    Provider::new().process_idempotent(store, f)
+}
+
+/// Process an AWS CloudFormation custom resource request at most once, using the given
+/// [`PhysicalIdStrategy`] to decide on the physical resource ID of the custom resource.
+///
+/// This behaves exactly like [`process_idempotent`], which uses the [`ArnStrategy`], but allows
+/// you to choose any other strategy.
+///
+/// [`PhysicalIdStrategy`]: trait.PhysicalIdStrategy.html
+/// [`process_idempotent`]: fn.process_idempotent.html
+/// [`ArnStrategy`]: struct.ArnStrategy.html
+pub fn process_idempotent_with_strategy<I, T, F, R, P, S>(
+    store: I,
+    strategy: T,
+    f: F,
+) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    I: IdempotencyStore + 'static,
+    T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+    R::Future: Send,
+    S: Serialize + Send + 'static,
+    P: SensitiveProperties + Send + 'static,
+{
+    Provider::new()
+        .strategy(strategy)
+        .process_idempotent(store, f)
+}
+
+impl<T> Provider<T> {
+    /// Process AWS CloudFormation custom resource requests at most once with this configuration,
+    /// as [`process_idempotent`] would.
+    ///
+    /// The response URL is checked before the request ID is claimed, such that a refused request
+    /// is not recorded.
+    ///
+    /// [`process_idempotent`]: fn.process_idempotent.html
+    pub fn process_idempotent<I, F, R, P, S>(
+        self,
+        store: I,
+        f: F,
+    ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        I: IdempotencyStore + 'static,
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+        R::Future: Send,
+        S: Serialize + Send + 'static,
+        P: SensitiveProperties + Send + 'static,
+    {
+        let (strategy, responder) = self.into_parts();
+        let store = Arc::new(store);
+        let f = Arc::new(f);
+        let strategy = Arc::new(strategy);
+        move |request: CfnRequest<P>| {
+            let span = request_span(&request);
+            let _entered = span.enter();
+            log_request(&request);
+            if let Err(e) = responder.check(request.response_url()) {
+                return Box::new(future::err(e));
+            }
+            let store = store.clone();
+            let f = f.clone();
+            let strategy = strategy.clone();
+            let responder = responder.clone();
+            let request_id = request.request_id().to_owned();
+            let response_url = request.response_url().to_owned();
+            let measured = responder.measure(&request);
+            Box::new(
+                store
+                    .claim(&request_id)
+                    .and_then(move |claim| match claim {
+                        Claim::Recorded(cfn_response) => {
+                            let result = match cfn_response {
+                                CfnResponse::Success { .. } => Ok(None),
+                                CfnResponse::Failed { ref reason, .. } => {
+                                    Err(format_err!("{}", reason))
+                                }
+                            };
+                            Either::A(
+                                responder
+                                    .send(response_url, &cfn_response, measured)
+                                    .and_then(move |_| result),
+                            )
+                        }
+                        Claim::InProgress => {
+                            // Whoever claimed the request responds once they are done.
+                            warn!("the request is already being processed, ignoring it");
+                            Either::B(Either::A(future::ok(None)))
+                        }
+                        Claim::Claimed => {
+                            let physical_resource_id =
+                                request.physical_resource_id_with(&*strategy);
+                            let response_fields =
+                                ResponseFields::new(&request, physical_resource_id);
+                            let sensitive_values = request.sensitive_values();
+                            Either::B(Either::B(f(request).into_future().then(
+                                move |request_result| {
+                                    let cfn_response = responder.response(
+                                        response_fields,
+                                        &request_result,
+                                        &sensitive_values,
+                                    );
+                                    // AWS CloudFormation is waiting for the response, thus it is
+                                    // sent even if it could not be recorded.
+                                    store.put(&request_id, &cfn_response).then(move |put| {
+                                        if let Err(e) = put {
+                                            error!(error = %e, "failed to record the response");
+                                        }
+                                        responder
+                                            .send(response_url, &cfn_response, measured)
+                                            .and_then(move |_| request_result)
+                                    })
+                                },
+                            )))
+                        }
+                    })
+                    .instrument(span.clone()),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn response(request_id: &str) -> CfnResponse {
+        CfnResponse::Failed {
+            reason: "reason".to_owned(),
+            request_id: request_id.to_owned(),
+            logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+            stack_id: "STACK-ID".to_owned(),
+            physical_resource_id: "PHYSICAL-RESOURCE-ID".to_owned(),
+        }
+    }
+
+    fn roundtrip<I>(store: &I)
+    where
+        I: IdempotencyStore,
+    {
+        assert_eq!(store.get("REQUEST-ID").wait().unwrap(), None);
+        store.put("REQUEST-ID", &response("first")).wait().unwrap();
+        store.put("REQUEST-ID", &response("second")).wait().unwrap();
+        store.put("../OTHER-ID", &response("other")).wait().unwrap();
+        assert_eq!(
+            store.get("REQUEST-ID").wait().unwrap(),
+            Some(response("second"))
+        );
+        assert_eq!(
+            store.get("../OTHER-ID").wait().unwrap(),
+            Some(response("other"))
+        );
+    }
+
+    fn claims<I>(store: &I)
+    where
+        I: IdempotencyStore,
+    {
+        assert_eq!(store.claim("REQUEST-ID").wait().unwrap(), Claim::Claimed);
+        assert_eq!(store.claim("REQUEST-ID").wait().unwrap(), Claim::InProgress);
+        assert_eq!(store.get("REQUEST-ID").wait().unwrap(), None);
+        store.put("REQUEST-ID", &response("first")).wait().unwrap();
+        assert_eq!(
+            store.claim("REQUEST-ID").wait().unwrap(),
+            Claim::Recorded(response("first"))
+        );
+    }
+
+    fn expired_claims<I>(store: &I)
+    where
+        I: IdempotencyStore,
+    {
+        assert_eq!(store.claim("REQUEST-ID").wait().unwrap(), Claim::Claimed);
+        assert_eq!(store.claim("REQUEST-ID").wait().unwrap(), Claim::Claimed);
+        store.put("REQUEST-ID", &response("first")).wait().unwrap();
+        assert_eq!(
+            store.claim("REQUEST-ID").wait().unwrap(),
+            Claim::Recorded(response("first"))
+        );
+    }
+
+    #[test]
+    fn memory_store() {
+        roundtrip(&MemoryStore::new());
+        claims(&MemoryStore::new());
+        expired_claims(&MemoryStore::new().claim_timeout(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn memory_store_capacity() {
+        let store = MemoryStore::with_capacity(2);
+        store.put("FIRST", &response("first")).wait().unwrap();
+        assert_eq!(store.claim("SECOND").wait().unwrap(), Claim::Claimed);
+        store.put("SECOND", &response("second")).wait().unwrap();
+        store.put("THIRD", &response("third")).wait().unwrap();
+        assert_eq!(store.get("FIRST").wait().unwrap(), None);
+        assert_eq!(
+            store.get("SECOND").wait().unwrap(),
+            Some(response("second"))
+        );
+        assert_eq!(store.get("THIRD").wait().unwrap(), Some(response("third")));
+    }
+
+    #[test]
+    fn file_store() {
+        let directory = env::temp_dir().join(format!(
+            "cfn-resource-provider-{}",
+            Uuid::new_v4().to_simple()
+        ));
+        roundtrip(&FileStore::new(&directory).unwrap());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+        fs::remove_dir_all(&directory).unwrap();
+
+        claims(&FileStore::new(&directory).unwrap());
+        // The claim file is removed once the response is recorded.
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+
+        expired_claims(
+            &FileStore::new(&directory)
+                .unwrap()
+                .claim_timeout(Duration::from_secs(0)),
+        );
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
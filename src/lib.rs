@@ -54,18 +54,94 @@
 //! cfn-resource-provider by you, as defined in the Apache-2.0 license, shall be dual licensed as
 //! above, without any additional terms or conditions.
 
+#[cfg(feature = "derive")]
+extern crate cfn_resource_provider_derive;
+#[macro_use]
 extern crate failure;
 extern crate futures;
+extern crate regex;
 extern crate reqwest;
+#[cfg(feature = "schemars")]
+extern crate schemars;
 #[macro_use]
 extern crate serde;
-#[cfg_attr(test, macro_use)]
+#[macro_use]
 extern crate serde_json;
+extern crate serde_path_to_error;
+#[cfg(feature = "template")]
+extern crate serde_yaml;
+extern crate sha2;
+#[cfg(test)]
+extern crate tokio_core;
+extern crate tokio_timer;
+#[macro_use]
+extern crate tracing;
+extern crate tracing_futures;
+extern crate uuid;
 
 use failure::Error;
 use futures::{Future, IntoFuture};
+use logging::{log_request, log_response, request_span, LoggedResponse};
 use serde::de::{Deserialize, Deserializer};
-use serde::ser::Serialize;
+use serde::ser::{Serialize, Serializer};
+use std::time::Instant;
+use tracing_futures::Instrument;
+
+mod coerce;
+mod continuation;
+mod create_only;
+mod custom_resource;
+mod http_client;
+mod idempotency;
+mod logging;
+mod metrics;
+mod physical_id;
+mod provider;
+mod raw;
+mod reason;
+mod redaction;
+mod response_url;
+#[cfg(feature = "schemars")]
+mod schema;
+mod stack_arn;
+#[cfg(feature = "template")]
+mod template;
+mod validate;
+mod waiter;
+
+pub use coerce::{deserialize_coerced, from_value_coerced};
+pub use continuation::{process_continuable, LocalScheduler, Progress, Scheduler};
+pub use create_only::{
+    enforce_create_only, CreateOnlyChanges, CreateOnlyPolicy, CreateOnlyProperties,
+};
+#[doc(hidden)]
+pub use custom_resource::__private;
+pub use custom_resource::{process_custom_resource, CustomResource};
+pub use http_client::HttpClient;
+pub use idempotency::{
+    process_idempotent, process_idempotent_with_strategy, Claim, FileStore, IdempotencyStore,
+    MemoryStore,
+};
+pub use metrics::Metrics;
+pub use physical_id::{
+    limit_physical_resource_id, ArnStrategy, ContentHashStrategy, PhysicalIdStrategy,
+    RandomUuidStrategy, ResourceTypeStrategy, StackNameStrategy, PHYSICAL_RESOURCE_ID_MAX_LENGTH,
+};
+pub use provider::Provider;
+pub use raw::process_json;
+pub use reason::ReasonFormat;
+pub use redaction::{Redactor, SensitiveProperties, SensitiveValues};
+pub use response_url::ResponseUrlPolicy;
+#[cfg(feature = "schemars")]
+pub use schema::resource_properties_schema;
+pub use stack_arn::StackArn;
+#[cfg(feature = "template")]
+pub use template::TemplateSnippet;
+pub use validate::{process_validated, Validate, ValidationError, Violation, Violations};
+pub use waiter::{Waiter, WaiterError};
+
+#[cfg(feature = "derive")]
+pub use cfn_resource_provider_derive::{custom_resource, PhysicalResourceIdSuffix};
 
 /// Every AWS CloudFormation resource, including custom resources, needs a unique physical resource
 /// ID. To aid in supplying this resource ID, your resource property type has to implement this
@@ -115,7 +191,30 @@ use serde::ser::Serialize;
 /// In this case `my_unique_parameter` is assumed to be the parameter that requires the custom
 /// resource implementation to create a new physical resource, thus the ID changes with it.
 ///
+/// ## Deriving
+///
+/// If the suffix consists only of property fields, you can enable the `derive` feature of this
+/// crate and derive this trait instead, marking the relevant fields with `#[suffix]`. Multiple
+/// fields are joined using a slash, which can be changed through `#[suffix(separator = "...")]` on
+/// the struct. Fields of type `Option<T>` are left out of the suffix if they are `None`.
+///
+/// ```ignore
+/// #[derive(PhysicalResourceIdSuffix)]
+/// struct MyResourcePropertiesType {
+///     #[suffix]
+///     my_unique_parameter: String,
+///     some_other_parameter: String,
+/// }
+/// ```
+///
+/// ## Length limit
+///
+/// AWS CloudFormation rejects physical resource IDs that are longer than 1 KB. Should the suffix
+/// you provide result in an ID exceeding this limit, the ID is shortened deterministically as
+/// described in [`limit_physical_resource_id`].
+///
 /// [`CfnResponse`]: enum.CfnResponse.html
+/// [`limit_physical_resource_id`]: fn.limit_physical_resource_id.html
 pub trait PhysicalResourceIdSuffixProvider {
     /// Creates a suffix that uniquely identifies the physical resource represented by the type
     /// holding the AWS CloudFormation resource properties.
@@ -155,6 +254,9 @@ impl PhysicalResourceIdSuffixProvider for () {
 /// `CfnRequest` has the generic type parameter `P` which the caller provides. This has to be a
 /// deserializable type.
 ///
+/// If `P` is serializable, `CfnRequest` can also be serialized back into the exact JSON form AWS
+/// CloudFormation sent it in, e.g. to log, persist or replay a request.
+///
 /// [docs.aws.amazon.com]: https://docs.aws.amazon.com/AWSCloudFormation/latest/UserGuide/crpg-ref-requests.html
 ///
 /// ## Example
@@ -239,12 +341,9 @@ impl PhysicalResourceIdSuffixProvider for () {
 ///
 /// [Custom Resource Reference]: https://docs.aws.amazon.com/AWSCloudFormation/latest/UserGuide/crpg-ref.html
 /// [CC BY-SA 4.0]: https://creativecommons.org/licenses/by-sa/4.0/
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(tag = "RequestType")]
-pub enum CfnRequest<P>
-where
-    P: Clone,
-{
+pub enum CfnRequest<P> {
     /// Custom resource provider requests with `RequestType` set to "`Create`" are sent when the
     /// template developer creates a stack that contains a custom resource. _See
     /// [docs.aws.amazon.com] for more information._
@@ -339,20 +438,31 @@ where
     },
 }
 
-impl<P> CfnRequest<P>
-where
-    P: PhysicalResourceIdSuffixProvider + Clone,
-{
+impl<P> CfnRequest<P> {
     /// The request ID field exists for all variants of the [`CfnRequest` enum]. This is a helper
     /// method to access this field without requiring you to match for the variant yourself.
     ///
     /// [`CfnRequest` enum]: enum.CfnRequest.html
     #[inline(always)]
-    pub fn request_id(&self) -> String {
+    pub fn request_id(&self) -> &str {
         match self {
-            CfnRequest::Create { request_id, .. } => request_id.to_owned(),
-            CfnRequest::Delete { request_id, .. } => request_id.to_owned(),
-            CfnRequest::Update { request_id, .. } => request_id.to_owned(),
+            CfnRequest::Create { request_id, .. } => request_id,
+            CfnRequest::Delete { request_id, .. } => request_id,
+            CfnRequest::Update { request_id, .. } => request_id,
+        }
+    }
+
+    /// The request type of the request, i.e. the variant of the [`CfnRequest` enum] as a
+    /// [`RequestType`].
+    ///
+    /// [`CfnRequest` enum]: enum.CfnRequest.html
+    /// [`RequestType`]: enum.RequestType.html
+    #[inline(always)]
+    pub fn request_type(&self) -> RequestType {
+        match self {
+            CfnRequest::Create { .. } => RequestType::Create,
+            CfnRequest::Delete { .. } => RequestType::Delete,
+            CfnRequest::Update { .. } => RequestType::Update,
         }
     }
 
@@ -361,11 +471,11 @@ where
     ///
     /// [`CfnRequest` enum]: enum.CfnRequest.html
     #[inline(always)]
-    pub fn response_url(&self) -> String {
+    pub fn response_url(&self) -> &str {
         match self {
-            CfnRequest::Create { response_url, .. } => response_url.to_owned(),
-            CfnRequest::Delete { response_url, .. } => response_url.to_owned(),
-            CfnRequest::Update { response_url, .. } => response_url.to_owned(),
+            CfnRequest::Create { response_url, .. } => response_url,
+            CfnRequest::Delete { response_url, .. } => response_url,
+            CfnRequest::Update { response_url, .. } => response_url,
         }
     }
 
@@ -374,11 +484,11 @@ where
     ///
     /// [`CfnRequest` enum]: enum.CfnRequest.html
     #[inline(always)]
-    pub fn resource_type(&self) -> String {
+    pub fn resource_type(&self) -> &str {
         match self {
-            CfnRequest::Create { resource_type, .. } => resource_type.to_owned(),
-            CfnRequest::Delete { resource_type, .. } => resource_type.to_owned(),
-            CfnRequest::Update { resource_type, .. } => resource_type.to_owned(),
+            CfnRequest::Create { resource_type, .. } => resource_type,
+            CfnRequest::Delete { resource_type, .. } => resource_type,
+            CfnRequest::Update { resource_type, .. } => resource_type,
         }
     }
 
@@ -387,20 +497,20 @@ where
     ///
     /// [`CfnRequest` enum]: enum.CfnRequest.html
     #[inline(always)]
-    pub fn logical_resource_id(&self) -> String {
+    pub fn logical_resource_id(&self) -> &str {
         match self {
             CfnRequest::Create {
                 logical_resource_id,
                 ..
-            } => logical_resource_id.to_owned(),
+            } => logical_resource_id,
             CfnRequest::Delete {
                 logical_resource_id,
                 ..
-            } => logical_resource_id.to_owned(),
+            } => logical_resource_id,
             CfnRequest::Update {
                 logical_resource_id,
                 ..
-            } => logical_resource_id.to_owned(),
+            } => logical_resource_id,
         }
     }
 
@@ -409,14 +519,73 @@ where
     ///
     /// [`CfnRequest` enum]: enum.CfnRequest.html
     #[inline(always)]
-    pub fn stack_id(&self) -> String {
+    pub fn stack_id(&self) -> &str {
         match self {
-            CfnRequest::Create { stack_id, .. } => stack_id.to_owned(),
-            CfnRequest::Delete { stack_id, .. } => stack_id.to_owned(),
-            CfnRequest::Update { stack_id, .. } => stack_id.to_owned(),
+            CfnRequest::Create { stack_id, .. } => stack_id,
+            CfnRequest::Delete { stack_id, .. } => stack_id,
+            CfnRequest::Update { stack_id, .. } => stack_id,
         }
     }
 
+    /// Parses the stack ID of the request into a [`StackArn`], giving access to the partition,
+    /// region, account, stack name and GUID of the stack the custom resource belongs to.
+    ///
+    /// This fails if the stack ID is not a well-formed AWS CloudFormation stack ARN.
+    ///
+    /// [`StackArn`]: struct.StackArn.html
+    pub fn stack_arn(&self) -> Result<StackArn, Error> {
+        self.stack_id().parse()
+    }
+
+    /// The resource properties field exists for all variants of the [`CfnRequest` enum]. This is a
+    /// helper method to access this field without requiring you to match for the variant yourself.
+    ///
+    /// [`CfnRequest` enum]: enum.CfnRequest.html
+    #[inline(always)]
+    pub fn resource_properties(&self) -> &P {
+        match self {
+            CfnRequest::Create {
+                resource_properties,
+                ..
+            } => resource_properties,
+            CfnRequest::Delete {
+                resource_properties,
+                ..
+            } => resource_properties,
+            CfnRequest::Update {
+                resource_properties,
+                ..
+            } => resource_properties,
+        }
+    }
+
+    /// Like [`physical_resource_id`], this retrieves the current physical resource ID for all
+    /// variants of the [`CfnRequest` enum], but uses the given [`PhysicalIdStrategy`] to generate
+    /// the ID for `Create` and `Update` requests.
+    ///
+    /// [`physical_resource_id`]: #method.physical_resource_id
+    /// [`CfnRequest` enum]: enum.CfnRequest.html
+    /// [`PhysicalIdStrategy`]: trait.PhysicalIdStrategy.html
+    pub fn physical_resource_id_with<T>(&self, strategy: &T) -> String
+    where
+        T: PhysicalIdStrategy<P> + ?Sized,
+    {
+        match self {
+            CfnRequest::Create { .. } | CfnRequest::Update { .. } => {
+                strategy.physical_resource_id(self)
+            }
+            CfnRequest::Delete {
+                physical_resource_id,
+                ..
+            } => physical_resource_id.to_owned(),
+        }
+    }
+}
+
+impl<P> CfnRequest<P>
+where
+    P: PhysicalResourceIdSuffixProvider,
+{
     /// The physical resource ID field either exists or has to be (re)generated for all variants of
     /// the [`CfnRequest` enum]. This is a helper method to access this field without requiring you
     /// to match for the variant yourself, while always getting the correct and up-to-date physical
@@ -438,14 +607,20 @@ where
                 resource_properties,
                 ..
             } => {
+                // Should the stack ID not be a well-formed stack ARN, we fall back to its last
+                // path segment, which is where the GUID would be located.
+                let stack_guid = match stack_id.parse::<StackArn>() {
+                    Ok(stack_arn) => stack_arn.guid().to_owned(),
+                    Err(_) => stack_id.rsplit('/').next().unwrap_or_default().to_owned(),
+                };
                 let suffix = resource_properties.physical_resource_id_suffix();
-                format!(
+                limit_physical_resource_id(format!(
                     "arn:custom:cfn-resource-provider:::{stack_id}-{logical_resource_id}{suffix_separator}{suffix}",
-                    stack_id = stack_id.rsplit('/').next().expect("failed to get GUID from stack ID"),
+                    stack_id = stack_guid,
                     logical_resource_id = logical_resource_id,
                     suffix_separator = if suffix.is_empty() { "" } else { "/" },
                     suffix = suffix,
-                )
+                ))
             }
             CfnRequest::Delete {
                 physical_resource_id,
@@ -454,28 +629,6 @@ where
         }
     }
 
-    /// The resource properties field exists for all variants of the [`CfnRequest` enum]. This is a
-    /// helper method to access this field without requiring you to match for the variant yourself.
-    ///
-    /// [`CfnRequest` enum]: enum.CfnRequest.html
-    #[inline(always)]
-    pub fn resource_properties(&self) -> &P {
-        match self {
-            CfnRequest::Create {
-                resource_properties,
-                ..
-            } => resource_properties,
-            CfnRequest::Delete {
-                resource_properties,
-                ..
-            } => resource_properties,
-            CfnRequest::Update {
-                resource_properties,
-                ..
-            } => resource_properties,
-        }
-    }
-
     /// This method turns a [`CfnRequest`] into a [`CfnResponse`], choosing one of the `Success` or
     /// `Failed` variants based on a `Result`. A [`CfnResponse`] should always be created through
     /// this method to ensure that all the relevant response-fields that AWS CloudFormation requires
@@ -486,29 +639,191 @@ where
     pub fn into_response<S>(self, result: &Result<Option<S>, Error>) -> CfnResponse
     where
         S: Serialize,
+    {
+        self.into_response_with(result, &ReasonFormat::default())
+    }
+
+    /// Like [`into_response`], this turns a [`CfnRequest`] into a [`CfnResponse`], but uses the
+    /// given [`ReasonFormat`] to derive the failure reason from the error.
+    ///
+    /// [`into_response`]: #method.into_response
+    /// [`CfnRequest`]: enum.CfnRequest.html
+    /// [`CfnResponse`]: enum.CfnResponse.html
+    /// [`ReasonFormat`]: struct.ReasonFormat.html
+    pub fn into_response_with<S>(
+        self,
+        result: &Result<Option<S>, Error>,
+        format: &ReasonFormat,
+    ) -> CfnResponse
+    where
+        S: Serialize,
+    {
+        let physical_resource_id = self.physical_resource_id();
+        ResponseFields::new(&self, physical_resource_id)
+            .into_response_with(result, |error| format.format(error))
+    }
+}
+
+/// The fields of a [`CfnResponse`] that are copied or derived from the request it responds to.
+///
+/// Extracting these fields up front allows the request, including its potentially large resource
+/// properties, to be moved into the handler instead of being cloned.
+///
+/// [`CfnResponse`]: enum.CfnResponse.html
+struct ResponseFields {
+    request_id: String,
+    logical_resource_id: String,
+    stack_id: String,
+    physical_resource_id: String,
+    no_echo: Option<bool>,
+}
+
+impl ResponseFields {
+    fn new<P>(request: &CfnRequest<P>, physical_resource_id: String) -> ResponseFields {
+        ResponseFields {
+            request_id: request.request_id().to_owned(),
+            logical_resource_id: request.logical_resource_id().to_owned(),
+            stack_id: request.stack_id().to_owned(),
+            physical_resource_id,
+            no_echo: None,
+        }
+    }
+
+    /// Sets `NoEcho` on a successful response, leaving it out otherwise.
+    fn no_echo(mut self, no_echo: bool) -> ResponseFields {
+        self.no_echo = if no_echo { Some(true) } else { None };
+        self
+    }
+
+    fn into_response_with<S, R>(self, result: &Result<Option<S>, Error>, reason: R) -> CfnResponse
+    where
+        S: Serialize,
+        R: FnOnce(&Error) -> String,
     {
         match result {
             Ok(data) => CfnResponse::Success {
-                request_id: self.request_id(),
-                logical_resource_id: self.logical_resource_id(),
-                stack_id: self.stack_id(),
-                physical_resource_id: self.physical_resource_id(),
-                no_echo: None,
+                request_id: self.request_id,
+                logical_resource_id: self.logical_resource_id,
+                stack_id: self.stack_id,
+                physical_resource_id: self.physical_resource_id,
+                no_echo: self.no_echo,
                 data: data
                     .as_ref()
                     .and_then(|value| serde_json::to_value(value).ok()),
             },
             Err(e) => CfnResponse::Failed {
-                reason: format!("{}", e),
-                request_id: self.request_id(),
-                logical_resource_id: self.logical_resource_id(),
-                stack_id: self.stack_id(),
-                physical_resource_id: self.physical_resource_id(),
+                reason: reason(e),
+                request_id: self.request_id,
+                logical_resource_id: self.logical_resource_id,
+                stack_id: self.stack_id,
+                physical_resource_id: self.physical_resource_id,
             },
         }
     }
 }
 
+/// The type of a request AWS CloudFormation sends to a custom resource, as indicated by the
+/// `RequestType` field of the request. Every variant corresponds to the [`CfnRequest`] variant of
+/// the same name.
+///
+/// [`CfnRequest`]: enum.CfnRequest.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Represents a [`CfnRequest::Create`](enum.CfnRequest.html#variant.Create) request.
+    Create,
+    /// Represents a [`CfnRequest::Delete`](enum.CfnRequest.html#variant.Delete) request.
+    Delete,
+    /// Represents a [`CfnRequest::Update`](enum.CfnRequest.html#variant.Update) request.
+    Update,
+}
+
+/// Generate the JSON document of an AWS CloudFormation custom resource request, as it would be
+/// sent to your custom resource.
+///
+/// This is meant to aid in writing test events, e.g. for invoking your custom resource locally or
+/// in the AWS Lambda console. The returned document uses the same field names [`CfnRequest`]
+/// expects during deserialization, and fills all fields AWS CloudFormation would provide itself
+/// (i.e. `RequestId`, `ResponseURL`, `StackId` and, where applicable, `PhysicalResourceId`) with
+/// placeholder values. Should you need specific values for these fields, e.g. a response URL
+/// pointing to a local server, you can overwrite them on the returned value.
+///
+/// `old_resource_properties` is only used for [`RequestType::Update`], where it is required.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # #[macro_use]
+/// # extern crate serde_json;
+/// # use cfn_resource_provider::*;
+/// # fn main() {
+/// let mut event = generate_request(
+///     RequestType::Update,
+///     "Custom::MyCustomResourceType",
+///     "MyResource",
+///     json!({ "key1": "new-string" }),
+///     Some(json!({ "key1": "string" })),
+/// ).unwrap();
+/// event["ResponseURL"] = json!("http://localhost:1234/response");
+///
+/// let request: CfnRequest<Ignored> = serde_json::from_value(event).unwrap();
+/// assert_eq!(request.logical_resource_id(), "MyResource");
+/// # }
+/// ```
+///
+/// [`CfnRequest`]: enum.CfnRequest.html
+/// [`RequestType::Update`]: enum.RequestType.html#variant.Update
+pub fn generate_request(
+    request_type: RequestType,
+    resource_type: &str,
+    logical_resource_id: &str,
+    resource_properties: serde_json::Value,
+    old_resource_properties: Option<serde_json::Value>,
+) -> Result<serde_json::Value, Error> {
+    const REQUEST_ID: &str = "00000000-0000-0000-0000-000000000000";
+    const RESPONSE_URL: &str =
+        "https://cloudformation-custom-resource-response-useast1.s3.amazonaws.com/presigned-url";
+    const STACK_GUID: &str = "00000000-0000-0000-0000-000000000000";
+
+    let mut request = serde_json::Map::new();
+    request.insert("RequestId".to_owned(), REQUEST_ID.into());
+    request.insert("ResponseURL".to_owned(), RESPONSE_URL.into());
+    request.insert("ResourceType".to_owned(), resource_type.into());
+    request.insert("LogicalResourceId".to_owned(), logical_resource_id.into());
+    request.insert(
+        "StackId".to_owned(),
+        format!(
+            "arn:aws:cloudformation:us-east-1:123456789012:stack/stack-name/{}",
+            STACK_GUID
+        )
+        .into(),
+    );
+    request.insert("ResourceProperties".to_owned(), resource_properties);
+
+    let physical_resource_id = format!(
+        "arn:custom:cfn-resource-provider:::{}-{}",
+        STACK_GUID, logical_resource_id
+    );
+    match request_type {
+        RequestType::Create => {
+            request.insert("RequestType".to_owned(), "Create".into());
+        }
+        RequestType::Delete => {
+            request.insert("RequestType".to_owned(), "Delete".into());
+            request.insert("PhysicalResourceId".to_owned(), physical_resource_id.into());
+        }
+        RequestType::Update => {
+            let old_resource_properties = old_resource_properties
+                .ok_or_else(|| format_err!("update requests require old resource properties"))?;
+            request.insert("RequestType".to_owned(), "Update".into());
+            request.insert("PhysicalResourceId".to_owned(), physical_resource_id.into());
+            request.insert("OldResourceProperties".to_owned(), old_resource_properties);
+        }
+    }
+
+    Ok(serde_json::Value::Object(request))
+}
+
 /// This is a special struct that can be used in conjunction with [Serde] to represent a field whose
 /// contents should be discarded during deserialization if it is present, and doesn't fail if the
 /// field doesn't exist.
@@ -517,6 +832,8 @@ where
 /// CloudFormation custom resource doesn't take any custom resource properties, but you don't want
 /// deserialization to fail should any properties be specified.
 ///
+/// Since any data is discarded on deserialization, `Ignored` serializes into `null`.
+///
 /// [Serde]: https://serde.rs/
 /// [`CfnRequest`]: enum.CfnRequest.html
 #[derive(Debug, Clone, Default, Copy, PartialEq)]
@@ -531,6 +848,15 @@ impl<'de> Deserialize<'de> for Ignored {
     }
 }
 
+impl Serialize for Ignored {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_none()
+    }
+}
+
 impl PhysicalResourceIdSuffixProvider for Ignored {
     fn physical_resource_id_suffix(&self) -> String {
         String::new()
@@ -542,6 +868,9 @@ impl PhysicalResourceIdSuffixProvider for Ignored {
 /// required JSON form, such that it can be sent to the pre-signed S3 response-URL provided by AWS
 /// CloudFormation without further modification.
 ///
+/// The response can be deserialized from that same JSON form, too, which allows tests and local
+/// simulators that capture the response sent to AWS CloudFormation to inspect it in its typed form.
+///
 /// This type should always be constructed from a [`CfnRequest`] using
 /// [`CfnRequest::into_response`][into_response] such that the response-fields are pre-filled with
 /// the expected values.
@@ -556,7 +885,7 @@ impl PhysicalResourceIdSuffixProvider for Ignored {
 ///
 /// [Custom Resource Reference]: https://docs.aws.amazon.com/AWSCloudFormation/latest/UserGuide/crpg-ref.html
 /// [CC BY-SA 4.0]: https://creativecommons.org/licenses/by-sa/4.0/
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(tag = "Status", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum CfnResponse {
     /// Indicates that the modification of the custom resource finished successfully.
@@ -614,6 +943,56 @@ pub enum CfnResponse {
     },
 }
 
+/// The status of a [`CfnResponse`], as indicated by the `Status` field of the response. Every
+/// variant corresponds to the [`CfnResponse`] variant of the same name.
+///
+/// [`CfnResponse`]: enum.CfnResponse.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseStatus {
+    /// Represents a [`CfnResponse::Success`](enum.CfnResponse.html#variant.Success) response.
+    Success,
+    /// Represents a [`CfnResponse::Failed`](enum.CfnResponse.html#variant.Failed) response.
+    Failed,
+}
+
+impl CfnResponse {
+    /// The status of the response, i.e. whether it represents a successful or a failed
+    /// modification of the custom resource.
+    #[inline(always)]
+    pub fn status(&self) -> ResponseStatus {
+        match self {
+            CfnResponse::Success { .. } => ResponseStatus::Success,
+            CfnResponse::Failed { .. } => ResponseStatus::Failed,
+        }
+    }
+
+    /// The reason field only exists for the `Failed` variant of the [`CfnResponse` enum]. This is
+    /// a helper method to access this field without requiring you to match for the variant
+    /// yourself, returning `None` for successful responses.
+    ///
+    /// [`CfnResponse` enum]: enum.CfnResponse.html
+    #[inline(always)]
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            CfnResponse::Success { .. } => None,
+            CfnResponse::Failed { reason, .. } => Some(reason),
+        }
+    }
+
+    /// The data field only exists for the `Success` variant of the [`CfnResponse` enum]. This is a
+    /// helper method to access this field without requiring you to match for the variant
+    /// yourself, returning `None` for failed responses or if no data was provided.
+    ///
+    /// [`CfnResponse` enum]: enum.CfnResponse.html
+    #[inline(always)]
+    pub fn data(&self) -> Option<&serde_json::Value> {
+        match self {
+            CfnResponse::Success { data, .. } => data.as_ref(),
+            CfnResponse::Failed { .. } => None,
+        }
+    }
+}
+
 /// Process an AWS CloudFormation custom resource request.
 ///
 /// This function will, in conjunction with [`rust-aws-lambda`][rust-aws-lambda], deserialize the
@@ -635,8 +1014,23 @@ pub enum CfnResponse {
 ///
 /// If your closure has errored, the failure reason will be extracted from the error you returned.
 /// If your closure succeeded, the positive return value will be serialized into the
-/// [`data` field][CfnResponse.Success.data] (unless the returned `Option` is `None`). (Specifying
-/// the [`no_echo` option] is currently not possible.)
+/// [`data` field][CfnResponse.Success.data] (unless the returned `Option` is `None`). Use
+/// [`Provider::no_echo`] to set the [`no_echo` option][CfnResponse.Success.no_echo].
+///
+/// Every request received and every response sent is logged as a structured [`tracing`] event,
+/// including the request type, the logical resource ID, the stack ID, the status and reason of the
+/// response and how long sending it took. Of the response data, only the names of the attributes
+/// are logged, never their values. The events are forwarded to [`log`] if no `tracing` subscriber
+/// is installed.
+///
+/// The processing of every request is covered by a `cfn_request` span, carrying the request type,
+/// request ID, resource type, logical resource ID and stack ID. Invoking your closure and sending
+/// the response happen in the child spans `handler` and `send_response` respectively.
+///
+/// The returned function builds an HTTP client when sending the first response and reuses it for
+/// all later ones, keeping the connection alive across the invocations of a warm AWS Lambda.
+///
+/// Use a [`Provider`] to configure how requests are processed, e.g. to emit [`Metrics`].
 ///
 /// ## Example
 ///
@@ -666,6 +1060,11 @@ pub enum CfnResponse {
 /// [CfnResponse]: enum.CfnRequest.html
 /// [CfnResponse.Success.data]: enum.CfnResponse.html#variant.Success.field.data
 /// [CfnResponse.Success.no_echo]: enum.CfnResponse.html#variant.Success.field.no_echo
+/// [`Provider::no_echo`]: struct.Provider.html#method.no_echo
+/// [`tracing`]: https://docs.rs/tracing/0.1
+/// [`log`]: https://docs.rs/log/0.4
+/// [`Provider`]: struct.Provider.html
+/// [`Metrics`]: struct.Metrics.html
 pub fn process<F, R, P, S>(
     f: F,
 ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
@@ -674,44 +1073,84 @@ where
     R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
     R::Future: Send,
     S: Serialize + Send + 'static,
-    P: PhysicalResourceIdSuffixProvider + Clone + Send + 'static,
+    P: PhysicalResourceIdSuffixProvider + Send + 'static,
 {
-    // The process below is a bit convoluted to read, the main reason for this is the following: we
-    // want to forward the response given by the closure `f` to our caller, while using that same
-    // response to inform AWS CloudFormation of the status of the custom resource.
-    //
-    // To accomplish this, we use a nested chain of futures that works as follows.
-    //
-    // 1. Call closure `f`.
-    // 2. Transform the initial request into a AWS CloudFormation response, deciding on success or
-    //    failure through the result returned by `f`.
-    // 3. Try to serialize and send the response to AWS CloudFormation (if this fails at any step,
-    //    propagate the error through to our caller).
-    // 4. If informing AWS CloudFormation succeeded, return the initial result of `f` to our caller.
-    move |request: CfnRequest<P>| {
-        let response_url = request.response_url();
-        Box::new(f(request.clone()).into_future().then(|request_result| {
-            let cfn_response = request.into_response(&request_result);
-            serde_json::to_string(&cfn_response)
-                .map_err(Into::into)
-                .into_future()
-                .and_then(|cfn_response| {
-                    reqwest::async::Client::builder()
-                        .build()
-                        .into_future()
-                        .and_then(move |client| {
-                            client
-                                .put(&response_url)
-                                .header("Content-Type", "")
-                                .body(cfn_response)
-                                .send()
-                        })
-                        .and_then(reqwest::async::Response::error_for_status)
-                        .map_err(Into::into)
-                })
-                .and_then(move |_| request_result)
-        }))
-    }
+    process_with_strategy(ArnStrategy, f)
+}
+
+/// Process an AWS CloudFormation custom resource request, using the given [`PhysicalIdStrategy`]
+/// to decide on the physical resource ID of the custom resource.
+///
+/// This behaves exactly like [`process`], which uses the [`ArnStrategy`], but allows you to choose
+/// any other strategy. Since the strategy decides on the physical resource ID, the resource
+/// properties do not have to implement [`PhysicalResourceIdSuffixProvider`].
+///
+/// ## Example
+///
+/// ```no_run
+/// extern crate aws_lambda as lambda;
+/// extern crate cfn_resource_provider as cfn;
+/// # type MyResourceProperties = ();
+///
+/// use cfn::*;
+///
+/// fn main() {
+///     lambda::start(cfn::process_with_strategy(
+///         RandomUuidStrategy,
+///         |event: CfnRequest<MyResourceProperties>| Ok(None::<()>),
+///     ));
+/// }
+/// ```
+///
+/// [`PhysicalIdStrategy`]: trait.PhysicalIdStrategy.html
+/// [`process`]: fn.process.html
+/// [`ArnStrategy`]: struct.ArnStrategy.html
+/// [`PhysicalResourceIdSuffixProvider`]: trait.PhysicalResourceIdSuffixProvider.html
+pub fn process_with_strategy<T, F, R, P, S>(
+    strategy: T,
+    f: F,
+) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+    R::Future: Send,
+    S: Serialize + Send + 'static,
+    P: Send + 'static,
+{
+    Provider::new().strategy(strategy).process(f)
+}
+
+/// Serializes the response and sends it to AWS CloudFormation using the pre-signed response URL.
+fn send_response(
+    http_client: &HttpClient,
+    response_url: String,
+    cfn_response: &CfnResponse,
+) -> impl Future<Item = (), Error = Error> + Send {
+    let span = info_span!("send_response", status = ?cfn_response.status());
+    let started = Instant::now();
+    let logged_response = LoggedResponse::new(cfn_response);
+    let http_client = http_client.clone();
+    serde_json::to_string(cfn_response)
+        .map_err(Into::into)
+        .into_future()
+        .and_then(move |cfn_response| {
+            http_client.client().into_future().and_then(move |client| {
+                client
+                    .put(&response_url)
+                    .header("Content-Type", "")
+                    .body(cfn_response)
+                    .send()
+                    .and_then(reqwest::async::Response::error_for_status)
+                    .map(|_| ())
+                    .map_err(Into::into)
+            })
+        })
+        .then(move |result| {
+            log_response(&logged_response, started.elapsed(), &result);
+            result
+        })
+        .instrument(span)
 }
 
 #[cfg(test)]
@@ -776,6 +1215,33 @@ mod test {
         assert!(request.physical_resource_id().ends_with("/STATIC-SUFFIX"));
     }
 
+    /// This test verifies that the physical resource ID does not exceed the length AWS
+    /// CloudFormation accepts, even if the suffix is overly long.
+    #[test]
+    fn long_suffix_is_limited() {
+        #[derive(Debug, Clone)]
+        struct LongSuffix;
+        impl PhysicalResourceIdSuffixProvider for LongSuffix {
+            fn physical_resource_id_suffix(&self) -> String {
+                "LONG-SUFFIX".repeat(1000)
+            }
+        }
+
+        let request: CfnRequest<LongSuffix> = CfnRequest::Create {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: String::new(),
+            logical_resource_id: "logical-id".to_owned(),
+            stack_id: "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid"
+                .to_owned(),
+            resource_properties: LongSuffix,
+        };
+        let physical_resource_id = request.physical_resource_id();
+        assert_eq!(physical_resource_id.len(), PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert!(physical_resource_id
+            .starts_with("arn:custom:cfn-resource-provider:::guid-logical-id/LONG-SUFFIX"));
+    }
+
     /// This is meant as a type-checking test: we want to ensure that we can provide a required type
     /// to `CfnRequest`.
     #[test]
@@ -1098,6 +1564,106 @@ mod test {
         assert_eq!(expected_request, actual_request);
     }
 
+    #[test]
+    fn cfnrequest_create_roundtrip() {
+        let expected_json = json!({
+            "RequestType" : "Create",
+            "RequestId" : "unique id for this create request",
+            "ResponseURL" : "pre-signed-url-for-create-response",
+            "ResourceType" : "Custom::MyCustomResourceType",
+            "LogicalResourceId" : "name of resource in template",
+            "StackId" : "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid",
+            "ResourceProperties" : {
+                "ExampleProperty1" : "example property 1",
+                "ExampleProperty2" : true
+            }
+        });
+
+        let request: CfnRequest<ExampleProperties> =
+            serde_json::from_value(expected_json.clone()).unwrap();
+        let actual_json = serde_json::to_value(&request).unwrap();
+        assert_eq!(expected_json, actual_json);
+
+        let actual_request: CfnRequest<ExampleProperties> =
+            serde_json::from_value(actual_json).unwrap();
+        assert_eq!(request, actual_request);
+    }
+
+    #[test]
+    fn cfnrequest_delete_roundtrip() {
+        let expected_json = json!({
+            "RequestType" : "Delete",
+            "RequestId" : "unique id for this delete request",
+            "ResponseURL" : "pre-signed-url-for-delete-response",
+            "ResourceType" : "Custom::MyCustomResourceType",
+            "LogicalResourceId" : "name of resource in template",
+            "StackId" : "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid",
+            "PhysicalResourceId" : "custom resource provider-defined physical id",
+            "ResourceProperties" : {
+                "ExampleProperty1" : "example property 1",
+                "ExampleProperty2" : null
+            }
+        });
+
+        let request: CfnRequest<ExampleProperties> =
+            serde_json::from_value(expected_json.clone()).unwrap();
+        let actual_json = serde_json::to_value(&request).unwrap();
+        assert_eq!(expected_json, actual_json);
+
+        let actual_request: CfnRequest<ExampleProperties> =
+            serde_json::from_value(actual_json).unwrap();
+        assert_eq!(request, actual_request);
+    }
+
+    #[test]
+    fn cfnrequest_update_roundtrip() {
+        let expected_json = json!({
+            "RequestType" : "Update",
+            "RequestId" : "unique id for this update request",
+            "ResponseURL" : "pre-signed-url-for-update-response",
+            "ResourceType" : "Custom::MyCustomResourceType",
+            "LogicalResourceId" : "name of resource in template",
+            "StackId" : "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid",
+            "PhysicalResourceId" : "custom resource provider-defined physical id",
+            "ResourceProperties" : {
+                "ExampleProperty1" : "new example property 1",
+                "ExampleProperty2" : false
+            },
+            "OldResourceProperties" : {
+                "ExampleProperty1" : "example property 1",
+                "ExampleProperty2" : null
+            }
+        });
+
+        let request: CfnRequest<ExampleProperties> =
+            serde_json::from_value(expected_json.clone()).unwrap();
+        let actual_json = serde_json::to_value(&request).unwrap();
+        assert_eq!(expected_json, actual_json);
+
+        let actual_request: CfnRequest<ExampleProperties> =
+            serde_json::from_value(actual_json).unwrap();
+        assert_eq!(request, actual_request);
+    }
+
+    #[test]
+    fn cfnrequest_ignored_roundtrip() {
+        let request: CfnRequest<Ignored> = serde_json::from_value(json!({
+            "RequestType" : "Create",
+            "RequestId" : "unique id for this create request",
+            "ResponseURL" : "pre-signed-url-for-create-response",
+            "ResourceType" : "Custom::MyCustomResourceType",
+            "LogicalResourceId" : "name of resource in template",
+            "StackId" : "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid",
+            "ResourceProperties" : {
+                "key1" : "string"
+            }
+        }))
+        .unwrap();
+        let actual_request: CfnRequest<Ignored> =
+            serde_json::from_value(serde_json::to_value(&request).unwrap()).unwrap();
+        assert_eq!(request, actual_request);
+    }
+
     #[test]
     fn cfnresponse_create_success_example() {
         let expected_response = json!({
@@ -1338,6 +1904,196 @@ mod test {
         assert_eq!(expected_response, actual_response);
     }
 
+    #[test]
+    fn cfnrequest_stack_arn() {
+        let request: CfnRequest<Empty> = CfnRequest::Create {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: String::new(),
+            logical_resource_id: "logical-id".to_owned(),
+            stack_id: "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid"
+                .to_owned(),
+            resource_properties: Empty,
+        };
+        let stack_arn = request.stack_arn().unwrap();
+        assert_eq!(stack_arn.region(), "us-east-2");
+        assert_eq!(stack_arn.account(), "123456789012");
+        assert_eq!(stack_arn.stack_name(), "stack-name");
+        assert_eq!(
+            request.physical_resource_id(),
+            "arn:custom:cfn-resource-provider:::guid-logical-id"
+        );
+    }
+
+    #[test]
+    fn cfnrequest_stack_arn_malformed() {
+        let request: CfnRequest<Empty> = CfnRequest::Create {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: String::new(),
+            logical_resource_id: "logical-id".to_owned(),
+            stack_id: "STACK-ID".to_owned(),
+            resource_properties: Empty,
+        };
+        assert!(request.stack_arn().is_err());
+        assert_eq!(
+            request.physical_resource_id(),
+            "arn:custom:cfn-resource-provider:::STACK-ID-logical-id"
+        );
+    }
+
+    #[test]
+    fn generate_request_create() {
+        let request: CfnRequest<ExampleProperties> = serde_json::from_value(
+            generate_request(
+                RequestType::Create,
+                "Custom::MyCustomResourceType",
+                "name of resource in template",
+                json!({ "ExampleProperty1": "example property 1" }),
+                None,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        match request {
+            CfnRequest::Create {
+                resource_type,
+                logical_resource_id,
+                resource_properties,
+                ..
+            } => {
+                assert_eq!(resource_type, "Custom::MyCustomResourceType");
+                assert_eq!(logical_resource_id, "name of resource in template");
+                assert_eq!(
+                    resource_properties,
+                    ExampleProperties {
+                        example_property_1: "example property 1".to_owned(),
+                        example_property_2: None,
+                    }
+                );
+            }
+            _ => panic!("expected create request"),
+        }
+    }
+
+    #[test]
+    fn generate_request_delete() {
+        let request: CfnRequest<ExampleProperties> = serde_json::from_value(
+            generate_request(
+                RequestType::Delete,
+                "Custom::MyCustomResourceType",
+                "name of resource in template",
+                json!({ "ExampleProperty1": "example property 1" }),
+                None,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        match request {
+            CfnRequest::Delete {
+                physical_resource_id,
+                ..
+            } => assert!(!physical_resource_id.is_empty()),
+            _ => panic!("expected delete request"),
+        }
+    }
+
+    #[test]
+    fn generate_request_update() {
+        let request: CfnRequest<ExampleProperties> = serde_json::from_value(
+            generate_request(
+                RequestType::Update,
+                "Custom::MyCustomResourceType",
+                "name of resource in template",
+                json!({ "ExampleProperty1": "new example property 1" }),
+                Some(json!({ "ExampleProperty1": "example property 1" })),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        match request {
+            CfnRequest::Update {
+                physical_resource_id,
+                resource_properties,
+                old_resource_properties,
+                ..
+            } => {
+                assert!(!physical_resource_id.is_empty());
+                assert_eq!(
+                    resource_properties.example_property_1,
+                    "new example property 1"
+                );
+                assert_eq!(
+                    old_resource_properties.example_property_1,
+                    "example property 1"
+                );
+            }
+            _ => panic!("expected update request"),
+        }
+    }
+
+    #[test]
+    fn generate_request_update_requires_old_properties() {
+        assert!(generate_request(
+            RequestType::Update,
+            "Custom::MyCustomResourceType",
+            "name of resource in template",
+            json!({ "ExampleProperty1": "example property 1" }),
+            None,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn cfnresponse_success_deserialize() {
+        let response: CfnResponse = serde_json::from_value(json!({
+            "Status" : "SUCCESS",
+            "RequestId" : "unique id for this create request (copied from request)",
+            "LogicalResourceId" : "name of resource in template (copied from request)",
+            "StackId" : "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid (copied from request)",
+            "PhysicalResourceId" : "required vendor-defined physical id that is unique for that vendor",
+            "Data" : {
+                "keyThatCanBeUsedInGetAtt1" : "data for key 1"
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(response.status(), ResponseStatus::Success);
+        assert_eq!(response.reason(), None);
+        assert_eq!(
+            response.data(),
+            Some(&json!({ "keyThatCanBeUsedInGetAtt1" : "data for key 1" }))
+        );
+        assert_eq!(
+            response,
+            CfnResponse::Success {
+                request_id: "unique id for this create request (copied from request)".to_owned(),
+                logical_resource_id: "name of resource in template (copied from request)".to_owned(),
+                stack_id: "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid (copied from request)".to_owned(),
+                physical_resource_id: "required vendor-defined physical id that is unique for that vendor".to_owned(),
+                no_echo: None,
+                data: Some(json!({ "keyThatCanBeUsedInGetAtt1" : "data for key 1" })),
+            }
+        );
+    }
+
+    #[test]
+    fn cfnresponse_failed_deserialize() {
+        let response: CfnResponse = serde_json::from_value(json!({
+            "Status" : "FAILED",
+            "Reason" : "Required failure reason string",
+            "RequestId" : "unique id for this create request (copied from request)",
+            "LogicalResourceId" : "name of resource in template (copied from request)",
+            "StackId" : "arn:aws:cloudformation:us-east-2:namespace:stack/stack-name/guid (copied from request)",
+            "PhysicalResourceId" : "required vendor-defined physical id that is unique for that vendor"
+        }))
+        .unwrap();
+
+        assert_eq!(response.status(), ResponseStatus::Failed);
+        assert_eq!(response.reason(), Some("Required failure reason string"));
+        assert_eq!(response.data(), None);
+    }
+
     #[test]
     fn cfnresponse_from_cfnrequest_unit() {
         let actual_request: CfnRequest<Ignored> = CfnRequest::Create {
@@ -0,0 +1,159 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::Span;
+use {CfnRequest, CfnResponse, ResponseStatus};
+
+/// Creates the span covering everything that happens while processing the request, i.e. invoking
+/// the handler and sending the response.
+pub(crate) fn request_span<P>(request: &CfnRequest<P>) -> Span {
+    info_span!(
+        "cfn_request",
+        request_type = ?request.request_type(),
+        request_id = request.request_id(),
+        resource_type = request.resource_type(),
+        logical_resource_id = request.logical_resource_id(),
+        stack_id = request.stack_id(),
+    )
+}
+
+/// Emits an event describing the request received.
+pub(crate) fn log_request<P>(request: &CfnRequest<P>) {
+    info!(
+        request_type = ?request.request_type(),
+        request_id = request.request_id(),
+        resource_type = request.resource_type(),
+        logical_resource_id = request.logical_resource_id(),
+        stack_id = request.stack_id(),
+        "received request"
+    );
+}
+
+/// The parts of a response that are logged once it has been sent. They are extracted before
+/// sending, as the future sending the response does not keep the response itself around.
+pub(crate) struct LoggedResponse {
+    request_id: String,
+    logical_resource_id: String,
+    stack_id: String,
+    status: ResponseStatus,
+    reason: Option<String>,
+    data_attributes: String,
+}
+
+impl LoggedResponse {
+    pub(crate) fn new(cfn_response: &CfnResponse) -> LoggedResponse {
+        let (request_id, logical_resource_id, stack_id) = match cfn_response {
+            CfnResponse::Success {
+                request_id,
+                logical_resource_id,
+                stack_id,
+                ..
+            }
+            | CfnResponse::Failed {
+                request_id,
+                logical_resource_id,
+                stack_id,
+                ..
+            } => (request_id, logical_resource_id, stack_id),
+        };
+        LoggedResponse {
+            request_id: request_id.clone(),
+            logical_resource_id: logical_resource_id.clone(),
+            stack_id: stack_id.clone(),
+            status: cfn_response.status(),
+            reason: cfn_response.reason().map(str::to_owned),
+            data_attributes: data_attributes(cfn_response),
+        }
+    }
+}
+
+/// Emits an event describing the response sent to AWS CloudFormation, or the failure to send it.
+pub(crate) fn log_response(
+    response: &LoggedResponse,
+    latency: Duration,
+    result: &Result<(), Error>,
+) {
+    let request_id = response.request_id.as_str();
+    let logical_resource_id = response.logical_resource_id.as_str();
+    let stack_id = response.stack_id.as_str();
+    let latency_ms = latency.as_millis() as u64;
+
+    if let Err(e) = result {
+        error!(
+            request_id,
+            logical_resource_id,
+            stack_id,
+            status = ?response.status,
+            latency_ms,
+            error = %e,
+            "failed to send response"
+        );
+        return;
+    }
+    match response.reason {
+        Some(ref reason) => warn!(
+            request_id,
+            logical_resource_id,
+            stack_id,
+            status = ?response.status,
+            reason = reason.as_str(),
+            latency_ms,
+            "sent response"
+        ),
+        None => info!(
+            request_id,
+            logical_resource_id,
+            stack_id,
+            status = ?response.status,
+            data_attributes = response.data_attributes.as_str(),
+            latency_ms,
+            "sent response"
+        ),
+    }
+}
+
+/// The names of the attributes in the response data, which is all that is logged of it. The
+/// values are left out, as they might well be secrets, whether or not `NoEcho` is set.
+fn data_attributes(cfn_response: &CfnResponse) -> String {
+    match cfn_response.data() {
+        Some(Value::Object(data)) => data
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn success(data: Value) -> CfnResponse {
+        CfnResponse::Success {
+            request_id: "REQUEST-ID".to_owned(),
+            logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+            stack_id: "STACK-ID".to_owned(),
+            physical_resource_id: "PHYSICAL-RESOURCE-ID".to_owned(),
+            no_echo: None,
+            data: Some(data),
+        }
+    }
+
+    #[test]
+    fn only_attribute_names_are_logged() {
+        assert_eq!(
+            data_attributes(&success(json!({ "Arn": "arn", "Password": "hunter2" }))),
+            "Arn, Password"
+        );
+        assert_eq!(data_attributes(&success(json!("hunter2"))), "");
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use serde_json::{self, Value};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use {RequestType, ResponseStatus};
+
+/// Metrics about every processed request, written in the [CloudWatch embedded metric format].
+///
+/// AWS Lambda forwards everything written to stdout to Amazon CloudWatch Logs, which extracts the
+/// metrics from the documents without requiring any further API calls. Every processed request
+/// results in a single JSON document on a line of its own, containing the following metrics, all
+/// of which are dimensioned by `ResourceType`:
+///
+/// * `Create`, `Update` and `Delete`: the number of requests of the respective type.
+/// * `Success` and `Failed`: the number of responses with the respective status.
+/// * `HandlerDuration`: the time your handler took, in milliseconds.
+/// * `ResponseDuration`: the time sending the response to AWS CloudFormation took, in milliseconds.
+///
+/// Use [`Provider::metrics`] to enable the metrics.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # use cfn_resource_provider::*;
+/// # fn main() {
+/// let processor = Provider::new()
+///     .metrics(Metrics::new("MyCustomResources"))
+///     .process(|event: CfnRequest<Ignored>| Ok(None::<()>));
+/// # }
+/// ```
+///
+/// [CloudWatch embedded metric format]: https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html
+/// [`Provider::metrics`]: struct.Provider.html#method.metrics
+#[derive(Clone)]
+pub struct Metrics {
+    namespace: String,
+    writer: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl Metrics {
+    /// Creates metrics in the given CloudWatch namespace, written to stdout.
+    pub fn new<N>(namespace: N) -> Metrics
+    where
+        N: Into<String>,
+    {
+        Metrics::with_writer(namespace, io::stdout())
+    }
+
+    /// Creates metrics in the given CloudWatch namespace, written to the given writer instead of
+    /// stdout, e.g. to capture them in tests.
+    pub fn with_writer<N, W>(namespace: N, writer: W) -> Metrics
+    where
+        N: Into<String>,
+        W: Write + Send + 'static,
+    {
+        Metrics {
+            namespace: namespace.into(),
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Writes the metrics of a single processed request.
+    pub(crate) fn record(&self, measurement: &Measurement) {
+        let mut line = match serde_json::to_vec(&self.document(measurement, SystemTime::now())) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(error = %e, "failed to serialize metrics");
+                return;
+            }
+        };
+        line.push(b'\n');
+        // A writer that panicked before must not fail every later invocation of a warm AWS Lambda.
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            warn!(error = %e, "failed to write metrics");
+        }
+    }
+
+    fn document(&self, measurement: &Measurement, timestamp: SystemTime) -> Value {
+        let count = |matches: bool| if matches { 1 } else { 0 };
+        json!({
+            "_aws": {
+                "Timestamp": timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+                "CloudWatchMetrics": [{
+                    "Namespace": self.namespace,
+                    "Dimensions": [["ResourceType"]],
+                    "Metrics": [
+                        { "Name": "Create", "Unit": "Count" },
+                        { "Name": "Update", "Unit": "Count" },
+                        { "Name": "Delete", "Unit": "Count" },
+                        { "Name": "Success", "Unit": "Count" },
+                        { "Name": "Failed", "Unit": "Count" },
+                        { "Name": "HandlerDuration", "Unit": "Milliseconds" },
+                        { "Name": "ResponseDuration", "Unit": "Milliseconds" },
+                    ],
+                }],
+            },
+            "ResourceType": measurement.resource_type,
+            "RequestId": measurement.request_id,
+            "Create": count(measurement.request_type == RequestType::Create),
+            "Update": count(measurement.request_type == RequestType::Update),
+            "Delete": count(measurement.request_type == RequestType::Delete),
+            "Success": count(measurement.status == ResponseStatus::Success),
+            "Failed": count(measurement.status == ResponseStatus::Failed),
+            "HandlerDuration": milliseconds(measurement.handler_duration),
+            "ResponseDuration": milliseconds(measurement.response_duration),
+        })
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+/// What was measured while processing a single request.
+pub(crate) struct Measurement {
+    pub(crate) resource_type: String,
+    pub(crate) request_id: String,
+    pub(crate) request_type: RequestType,
+    pub(crate) status: ResponseStatus,
+    pub(crate) handler_duration: Duration,
+    pub(crate) response_duration: Duration,
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn measurement() -> Measurement {
+        Measurement {
+            resource_type: "Custom::Thing".to_owned(),
+            request_id: "REQUEST-ID".to_owned(),
+            request_type: RequestType::Update,
+            status: ResponseStatus::Failed,
+            handler_duration: Duration::from_micros(1500),
+            response_duration: Duration::from_millis(20),
+        }
+    }
+
+    #[test]
+    fn document() {
+        let metrics = Metrics::with_writer("Namespace", io::sink());
+        let document = metrics.document(&measurement(), UNIX_EPOCH + Duration::from_secs(1));
+
+        assert_eq!(document["_aws"]["Timestamp"], 1000);
+        assert_eq!(
+            document["_aws"]["CloudWatchMetrics"][0]["Namespace"],
+            "Namespace"
+        );
+        assert_eq!(
+            document["_aws"]["CloudWatchMetrics"][0]["Dimensions"],
+            json!([["ResourceType"]])
+        );
+        assert_eq!(document["ResourceType"], "Custom::Thing");
+        assert_eq!(document["Create"], 0);
+        assert_eq!(document["Update"], 1);
+        assert_eq!(document["Delete"], 0);
+        assert_eq!(document["Success"], 0);
+        assert_eq!(document["Failed"], 1);
+        assert_eq!(document["HandlerDuration"], 1.5);
+        assert_eq!(document["ResponseDuration"], 20.0);
+    }
+
+    #[test]
+    fn every_metric_has_a_value() {
+        let metrics = Metrics::with_writer("Namespace", io::sink());
+        let document = metrics.document(&measurement(), SystemTime::now());
+        for metric in document["_aws"]["CloudWatchMetrics"][0]["Metrics"]
+            .as_array()
+            .unwrap()
+        {
+            let name = metric["Name"].as_str().unwrap();
+            assert!(document[name].is_number(), "missing value for {}", name);
+        }
+    }
+
+    /// Panics on the first write, records every later one.
+    struct PanicOnce {
+        panicked: bool,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for PanicOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if !self.panicked {
+                self.panicked = true;
+                panic!("the writer panicked");
+            }
+            self.written.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn panicking_writer_does_not_poison_later_records() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let metrics = Metrics::with_writer(
+            "Namespace",
+            PanicOnce {
+                panicked: false,
+                written: written.clone(),
+            },
+        );
+        let panicking = metrics.clone();
+        assert!(
+            ::std::thread::spawn(move || panicking.record(&measurement()))
+                .join()
+                .is_err()
+        );
+
+        metrics.record(&measurement());
+        assert!(!written.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,474 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+use uuid::Uuid;
+use {CfnRequest, PhysicalResourceIdSuffixProvider};
+
+/// The maximum length in bytes AWS CloudFormation accepts for a physical resource ID.
+pub const PHYSICAL_RESOURCE_ID_MAX_LENGTH: usize = 1024;
+
+/// Ensures that a physical resource ID does not exceed [`PHYSICAL_RESOURCE_ID_MAX_LENGTH`].
+///
+/// Physical resource IDs that are within the limit are returned unmodified. Longer IDs are
+/// truncated, and the hex-encoded SHA-256 hash of the complete ID is appended (separated by a
+/// slash), such that the resulting ID fits within the maximum length:
+///
+/// ```text
+/// {truncated physical resource ID}/{sha256 of the complete physical resource ID}
+/// ```
+///
+/// This keeps the shortened ID deterministic, i.e. the same long ID will always result in the same
+/// shortened ID, and unique, since IDs that only differ after the point of truncation still differ
+/// in their hash.
+///
+/// [`CfnRequest::physical_resource_id`] applies this to every ID it generates, you only have to
+/// call this yourself if you construct physical resource IDs by other means.
+///
+/// ## Example
+///
+/// ```
+/// # use cfn_resource_provider::*;
+/// let short_id = "arn:custom:cfn-resource-provider:::guid-logical-id/suffix".to_owned();
+/// assert_eq!(limit_physical_resource_id(short_id.clone()), short_id);
+///
+/// let long_id = format!("arn:custom:cfn-resource-provider:::guid-logical-id/{}", "a".repeat(2000));
+/// assert_eq!(
+///     limit_physical_resource_id(long_id).len(),
+///     PHYSICAL_RESOURCE_ID_MAX_LENGTH,
+/// );
+/// ```
+///
+/// [`PHYSICAL_RESOURCE_ID_MAX_LENGTH`]: constant.PHYSICAL_RESOURCE_ID_MAX_LENGTH.html
+/// [`CfnRequest::physical_resource_id`]: enum.CfnRequest.html#method.physical_resource_id
+pub fn limit_physical_resource_id(physical_resource_id: String) -> String {
+    if physical_resource_id.len() <= PHYSICAL_RESOURCE_ID_MAX_LENGTH {
+        return physical_resource_id;
+    }
+
+    let hash = sha256_hex(physical_resource_id.as_bytes());
+
+    // The truncated ID has to end on a character boundary, which might leave the result a few
+    // bytes shorter than the maximum length for multi-byte characters.
+    let mut truncated_length = PHYSICAL_RESOURCE_ID_MAX_LENGTH - hash.len() - 1;
+    while !physical_resource_id.is_char_boundary(truncated_length) {
+        truncated_length -= 1;
+    }
+
+    format!("{}/{}", &physical_resource_id[..truncated_length], hash)
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hash = String::with_capacity(64);
+    for byte in Sha256::digest(data).iter() {
+        write!(hash, "{:02x}", byte).expect("writing to a string cannot fail");
+    }
+    hash
+}
+
+/// A strategy that decides which physical resource ID a custom resource receives.
+///
+/// The strategy is consulted for `Create` and `Update` requests. For `Update` requests, returning
+/// an ID that differs from the current one causes AWS CloudFormation to treat the update as a
+/// replacement, i.e. it will issue a `Delete` request for the previous physical resource once the
+/// stack update completes. `Delete` requests always use the physical resource ID provided by AWS
+/// CloudFormation, and will thus never consult the strategy.
+///
+/// The following strategies are included in this library:
+///
+/// * [`ArnStrategy`]: the default strategy, building an ARN-like ID from the stack, the logical ID
+///   and the suffix provided through [`PhysicalResourceIdSuffixProvider`].
+/// * [`RandomUuidStrategy`]: a random UUID for every created resource.
+/// * [`ContentHashStrategy`]: a hash of selected resource properties.
+/// * [`StackNameStrategy`]: a readable ID prefixed with the stack name.
+/// * [`ResourceTypeStrategy`]: selects one of the above (or your own) per resource type.
+///
+/// All of them limit the IDs they generate using [`limit_physical_resource_id`].
+///
+/// You can select the strategy used for a custom resource through [`process_with_strategy`].
+///
+/// [`ArnStrategy`]: struct.ArnStrategy.html
+/// [`RandomUuidStrategy`]: struct.RandomUuidStrategy.html
+/// [`ContentHashStrategy`]: struct.ContentHashStrategy.html
+/// [`StackNameStrategy`]: struct.StackNameStrategy.html
+/// [`ResourceTypeStrategy`]: struct.ResourceTypeStrategy.html
+/// [`PhysicalResourceIdSuffixProvider`]: trait.PhysicalResourceIdSuffixProvider.html
+/// [`limit_physical_resource_id`]: fn.limit_physical_resource_id.html
+/// [`process_with_strategy`]: fn.process_with_strategy.html
+pub trait PhysicalIdStrategy<P> {
+    /// Creates the physical resource ID for the resource the `Create` or `Update` request refers
+    /// to.
+    fn physical_resource_id(&self, request: &CfnRequest<P>) -> String;
+}
+
+/// The physical resource ID of an `Update` request, if the request is one.
+fn current_physical_resource_id<P>(request: &CfnRequest<P>) -> Option<&str> {
+    match request {
+        CfnRequest::Update {
+            physical_resource_id,
+            ..
+        } => Some(physical_resource_id),
+        _ => None,
+    }
+}
+
+/// The strategy building physical resource IDs of the following form, as documented on
+/// [`PhysicalResourceIdSuffixProvider`]:
+///
+/// ```text
+/// arn:custom:cfn-resource-provider:::{stack_id}-{logical_resource_id}/{suffix}
+/// ```
+///
+/// This is the strategy used by [`process`] and [`CfnRequest::physical_resource_id`].
+///
+/// [`PhysicalResourceIdSuffixProvider`]: trait.PhysicalResourceIdSuffixProvider.html
+/// [`process`]: fn.process.html
+/// [`CfnRequest::physical_resource_id`]: enum.CfnRequest.html#method.physical_resource_id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArnStrategy;
+
+impl<P> PhysicalIdStrategy<P> for ArnStrategy
+where
+    P: PhysicalResourceIdSuffixProvider,
+{
+    fn physical_resource_id(&self, request: &CfnRequest<P>) -> String {
+        request.physical_resource_id()
+    }
+}
+
+/// The strategy assigning a random UUID (version 4) as the physical resource ID of every created
+/// resource.
+///
+/// The ID is kept on updates, i.e. updates never cause a replacement of the resource.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RandomUuidStrategy;
+
+impl<P> PhysicalIdStrategy<P> for RandomUuidStrategy {
+    fn physical_resource_id(&self, request: &CfnRequest<P>) -> String {
+        match current_physical_resource_id(request) {
+            Some(physical_resource_id) => physical_resource_id.to_owned(),
+            None => Uuid::new_v4().to_hyphenated().to_string(),
+        }
+    }
+}
+
+/// The strategy deriving the physical resource ID from a hash of selected resource properties.
+///
+/// The selector you provide returns the JSON value of the properties that should be part of the
+/// hash. The resulting ID has the following form, where the hash is the hex-encoded SHA-256 hash
+/// over the stack ID, the logical resource ID and the selected properties:
+///
+/// ```text
+/// {logical_resource_id}-{hash}
+/// ```
+///
+/// Since the ID is regenerated on updates, changing any of the selected properties causes a
+/// replacement of the resource.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # #[macro_use]
+/// # extern crate serde_json;
+/// # use cfn_resource_provider::*;
+/// struct MyResourceProperties {
+///     name: String,
+///     size: u32,
+///     tags: Vec<String>,
+/// }
+///
+/// # fn main() {
+/// // Only changes to the name and the size should replace the resource.
+/// let strategy = ContentHashStrategy::new(|properties: &MyResourceProperties| {
+///     json!([properties.name, properties.size])
+/// });
+/// # let _ = strategy;
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ContentHashStrategy<F> {
+    selector: F,
+}
+
+impl<F> ContentHashStrategy<F> {
+    /// Creates the strategy with the given selector, which receives the resource properties and
+    /// returns the parts of them that the hash should be computed over.
+    pub fn new(selector: F) -> ContentHashStrategy<F> {
+        ContentHashStrategy { selector }
+    }
+}
+
+impl<P, F> PhysicalIdStrategy<P> for ContentHashStrategy<F>
+where
+    F: Fn(&P) -> serde_json::Value,
+{
+    fn physical_resource_id(&self, request: &CfnRequest<P>) -> String {
+        let content = serde_json::to_string(&json!([
+            request.stack_id(),
+            request.logical_resource_id(),
+            (self.selector)(request.resource_properties()),
+        ]))
+        .expect("serializing a JSON value cannot fail");
+        limit_physical_resource_id(format!(
+            "{}-{}",
+            request.logical_resource_id(),
+            sha256_hex(content.as_bytes()),
+        ))
+    }
+}
+
+/// The strategy assigning readable physical resource IDs prefixed with the stack name, similar to
+/// the names AWS CloudFormation generates for resources itself:
+///
+/// ```text
+/// {stack_name}-{logical_resource_id}-{random}
+/// ```
+///
+/// `random` consists of twelve random uppercase alphanumeric characters, keeping the ID unique if
+/// a stack of the same name is created again. Should the stack ID not be a well-formed stack ARN,
+/// the complete stack ID is used in place of the stack name.
+///
+/// The ID is kept on updates, i.e. updates never cause a replacement of the resource.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StackNameStrategy;
+
+impl<P> PhysicalIdStrategy<P> for StackNameStrategy {
+    fn physical_resource_id(&self, request: &CfnRequest<P>) -> String {
+        if let Some(physical_resource_id) = current_physical_resource_id(request) {
+            return physical_resource_id.to_owned();
+        }
+
+        let stack_name = match request.stack_arn() {
+            Ok(stack_arn) => stack_arn.stack_name().to_owned(),
+            Err(_) => request.stack_id().to_owned(),
+        };
+        let random = Uuid::new_v4().to_simple().to_string()[..12].to_uppercase();
+        limit_physical_resource_id(format!(
+            "{}-{}-{}",
+            stack_name,
+            request.logical_resource_id(),
+            random
+        ))
+    }
+}
+
+/// The strategy selecting another strategy based on the resource type of the request, falling back
+/// to a default strategy for any resource type that has no strategy registered.
+///
+/// ## Example
+///
+/// ```
+/// # use cfn_resource_provider::*;
+/// let strategy: ResourceTypeStrategy<Ignored> = ResourceTypeStrategy::new(ArnStrategy)
+///     .with("Custom::Bucket", StackNameStrategy)
+///     .with("Custom::Token", RandomUuidStrategy);
+/// # let _ = strategy;
+/// ```
+pub struct ResourceTypeStrategy<P> {
+    default: Box<dyn PhysicalIdStrategy<P> + Send + Sync>,
+    strategies: HashMap<String, Box<dyn PhysicalIdStrategy<P> + Send + Sync>>,
+}
+
+impl<P> ResourceTypeStrategy<P> {
+    /// Creates the strategy, using `default` for every resource type that has no strategy
+    /// registered through [`with`](#method.with).
+    pub fn new<T>(default: T) -> ResourceTypeStrategy<P>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+    {
+        ResourceTypeStrategy {
+            default: Box::new(default),
+            strategies: HashMap::new(),
+        }
+    }
+
+    /// Registers the strategy to use for requests of the given resource type, replacing any
+    /// strategy previously registered for it.
+    pub fn with<T>(mut self, resource_type: &str, strategy: T) -> ResourceTypeStrategy<P>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+    {
+        self.strategies
+            .insert(resource_type.to_owned(), Box::new(strategy));
+        self
+    }
+}
+
+impl<P> PhysicalIdStrategy<P> for ResourceTypeStrategy<P> {
+    fn physical_resource_id(&self, request: &CfnRequest<P>) -> String {
+        self.strategies
+            .get(request.resource_type())
+            .unwrap_or(&self.default)
+            .physical_resource_id(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Ignored;
+
+    fn create_request<P>(resource_type: &str, resource_properties: P) -> CfnRequest<P> {
+        CfnRequest::Create {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: resource_type.to_owned(),
+            logical_resource_id: "logical-id".to_owned(),
+            stack_id: "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid"
+                .to_owned(),
+            resource_properties,
+        }
+    }
+
+    fn update_request<P>(resource_properties: P, old_resource_properties: P) -> CfnRequest<P> {
+        CfnRequest::Update {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: String::new(),
+            logical_resource_id: "logical-id".to_owned(),
+            stack_id: "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid"
+                .to_owned(),
+            physical_resource_id: "current-physical-id".to_owned(),
+            resource_properties,
+            old_resource_properties,
+        }
+    }
+
+    #[test]
+    fn arn_strategy() {
+        assert_eq!(
+            ArnStrategy.physical_resource_id(&create_request("", Ignored)),
+            "arn:custom:cfn-resource-provider:::guid-logical-id"
+        );
+    }
+
+    #[test]
+    fn random_uuid_strategy() {
+        let request = create_request("", Ignored);
+        let first = RandomUuidStrategy.physical_resource_id(&request);
+        let second = RandomUuidStrategy.physical_resource_id(&request);
+        assert_eq!(first.len(), 36);
+        assert_ne!(first, second);
+        assert_eq!(
+            RandomUuidStrategy.physical_resource_id(&update_request(Ignored, Ignored)),
+            "current-physical-id"
+        );
+    }
+
+    #[test]
+    fn content_hash_strategy() {
+        let strategy = ContentHashStrategy::new(|properties: &(u32, u32)| json!(properties.0));
+        let id = strategy.physical_resource_id(&create_request("", (1, 1)));
+        assert!(id.starts_with("logical-id-"));
+        assert_eq!(
+            id,
+            strategy.physical_resource_id(&create_request("", (1, 2)))
+        );
+        assert_ne!(
+            id,
+            strategy.physical_resource_id(&create_request("", (2, 1)))
+        );
+        assert_eq!(
+            id,
+            strategy.physical_resource_id(&update_request((1, 3), (2, 2)))
+        );
+    }
+
+    #[test]
+    fn stack_name_strategy() {
+        let request = create_request("", Ignored);
+        let first = StackNameStrategy.physical_resource_id(&request);
+        let second = StackNameStrategy.physical_resource_id(&request);
+        assert!(first.starts_with("stack-name-logical-id-"));
+        assert_eq!(first.len(), "stack-name-logical-id-".len() + 12);
+        assert_ne!(first, second);
+        assert_eq!(
+            StackNameStrategy.physical_resource_id(&update_request(Ignored, Ignored)),
+            "current-physical-id"
+        );
+    }
+
+    #[test]
+    fn stack_name_strategy_is_limited() {
+        let mut request = create_request("", Ignored);
+        if let CfnRequest::Create {
+            ref mut logical_resource_id,
+            ..
+        } = request
+        {
+            *logical_resource_id = "a".repeat(2 * PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        }
+        let id = StackNameStrategy.physical_resource_id(&request);
+        assert!(id.len() <= PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert_ne!(id, StackNameStrategy.physical_resource_id(&request));
+    }
+
+    #[test]
+    fn resource_type_strategy() {
+        let strategy = ResourceTypeStrategy::new(ArnStrategy)
+            .with("Custom::Uuid", RandomUuidStrategy)
+            .with("Custom::StackName", StackNameStrategy);
+        assert_eq!(
+            strategy.physical_resource_id(&create_request("Custom::Other", Ignored)),
+            "arn:custom:cfn-resource-provider:::guid-logical-id"
+        );
+        assert_eq!(
+            strategy
+                .physical_resource_id(&create_request("Custom::Uuid", Ignored))
+                .len(),
+            36
+        );
+        assert!(strategy
+            .physical_resource_id(&create_request("Custom::StackName", Ignored))
+            .starts_with("stack-name-logical-id-"));
+    }
+
+    #[test]
+    fn short_id_is_unmodified() {
+        let id = "a".repeat(PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert_eq!(limit_physical_resource_id(id.clone()), id);
+    }
+
+    #[test]
+    fn long_id_is_limited() {
+        let id = "a".repeat(PHYSICAL_RESOURCE_ID_MAX_LENGTH + 1);
+        let limited_id = limit_physical_resource_id(id.clone());
+        assert_eq!(limited_id.len(), PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert!(id.starts_with(&limited_id[..limited_id.len() - 65]));
+    }
+
+    #[test]
+    fn long_id_is_deterministic() {
+        let id = "a".repeat(2 * PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert_eq!(
+            limit_physical_resource_id(id.clone()),
+            limit_physical_resource_id(id)
+        );
+    }
+
+    #[test]
+    fn long_ids_with_common_prefix_are_unique() {
+        let prefix = "a".repeat(2 * PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert_ne!(
+            limit_physical_resource_id(format!("{}b", prefix)),
+            limit_physical_resource_id(format!("{}c", prefix))
+        );
+    }
+
+    #[test]
+    fn long_id_is_truncated_at_char_boundary() {
+        let id = "ä".repeat(PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        let limited_id = limit_physical_resource_id(id);
+        assert!(limited_id.len() <= PHYSICAL_RESOURCE_ID_MAX_LENGTH);
+        assert!(limited_id.len() >= PHYSICAL_RESOURCE_ID_MAX_LENGTH - 1);
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use metrics::Measurement;
+use serde::ser::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing_futures::Instrument;
+use {
+    log_request, request_span, send_response, ArnStrategy, CfnRequest, CfnResponse, HttpClient,
+    Metrics, PhysicalIdStrategy, ReasonFormat, Redactor, RequestType, ResponseFields,
+    ResponseUrlPolicy, SensitiveProperties, SensitiveValues,
+};
+
+/// A configurable variant of [`process`], for when the defaults do not fit.
+///
+/// `Provider::new().process(f)` behaves exactly like `process(f)`. The builder methods change
+/// individual aspects of how requests are processed and responses are sent. The same
+/// configuration applies to [`process_custom_resource`], [`process_idempotent`],
+/// [`process_continuable`], [`process_json`] and [`enforce_create_only`], which are available as
+/// methods of the provider as well.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # use cfn_resource_provider::*;
+/// # fn main() {
+/// let processor = Provider::new()
+///     .strategy(RandomUuidStrategy)
+///     .metrics(Metrics::new("MyCustomResources"))
+///     .process(|event: CfnRequest<Ignored>| Ok(None::<()>));
+/// # }
+/// ```
+///
+/// [`process`]: fn.process.html
+/// [`process_custom_resource`]: #method.process_custom_resource
+/// [`process_idempotent`]: #method.process_idempotent
+/// [`process_continuable`]: #method.process_continuable
+/// [`process_json`]: #method.process_json
+/// [`enforce_create_only`]: #method.enforce_create_only
+#[derive(Debug, Clone)]
+pub struct Provider<T = ArnStrategy> {
+    strategy: T,
+    metrics: Option<Metrics>,
+    response_url_policy: Option<ResponseUrlPolicy>,
+    redactor: Option<Redactor>,
+    reason_format: ReasonFormat,
+    http_client: HttpClient,
+    no_echo: bool,
+}
+
+impl Default for Provider {
+    fn default() -> Provider {
+        Provider {
+            strategy: ArnStrategy,
+            metrics: None,
+            response_url_policy: None,
+            redactor: None,
+            reason_format: ReasonFormat::default(),
+            http_client: HttpClient::default(),
+            no_echo: false,
+        }
+    }
+}
+
+impl Provider {
+    /// Creates a provider with the same behaviour as [`process`].
+    ///
+    /// [`process`]: fn.process.html
+    pub fn new() -> Provider {
+        Provider::default()
+    }
+}
+
+impl<T> Provider<T> {
+    /// Uses the given [`PhysicalIdStrategy`] to decide on the physical resource ID of the custom
+    /// resource, the [`ArnStrategy`] by default.
+    ///
+    /// [`PhysicalIdStrategy`]: trait.PhysicalIdStrategy.html
+    /// [`ArnStrategy`]: struct.ArnStrategy.html
+    pub fn strategy<U>(self, strategy: U) -> Provider<U> {
+        Provider {
+            strategy,
+            metrics: self.metrics,
+            response_url_policy: self.response_url_policy,
+            redactor: self.redactor,
+            reason_format: self.reason_format,
+            http_client: self.http_client,
+            no_echo: self.no_echo,
+        }
+    }
+
+    /// Emits [`Metrics`] about every request responded to, none by default.
+    ///
+    /// [`Metrics`]: struct.Metrics.html
+    pub fn metrics(mut self, metrics: Metrics) -> Provider<T> {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Only sends responses to response URLs allowed by the given [`ResponseUrlPolicy`], to any
+    /// response URL by default.
+    ///
+    /// If the response URL of a request is not allowed, the request is neither passed to your
+    /// closure nor responded to, an error is returned instead.
+    ///
+    /// [`ResponseUrlPolicy`]: struct.ResponseUrlPolicy.html
+    pub fn response_url_policy(mut self, policy: ResponseUrlPolicy) -> Provider<T> {
+        self.response_url_policy = Some(policy);
+        self
+    }
+
+    /// Redacts secrets from the failure reasons sent to AWS CloudFormation using the given
+    /// [`Redactor`], nothing is redacted by default.
+    ///
+    /// Only the reason sent to AWS CloudFormation is redacted, the error returned to the caller is
+    /// left as is.
+    ///
+    /// [`Redactor`]: struct.Redactor.html
+    pub fn redactor(mut self, redactor: Redactor) -> Provider<T> {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Derives the failure reasons sent to AWS CloudFormation from errors using the given
+    /// [`ReasonFormat`], by default only using the message of the error itself.
+    ///
+    /// [`ReasonFormat`]: struct.ReasonFormat.html
+    pub fn reason_format(mut self, reason_format: ReasonFormat) -> Provider<T> {
+        self.reason_format = reason_format;
+        self
+    }
+
+    /// Sends the responses to AWS CloudFormation using the given [`HttpClient`], one with the
+    /// defaults of `reqwest` otherwise.
+    ///
+    /// Either way, the client is built once and shared by all requests processed.
+    ///
+    /// [`HttpClient`]: struct.HttpClient.html
+    pub fn http_client(mut self, http_client: HttpClient) -> Provider<T> {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Sets `NoEcho` on successful responses, such that AWS CloudFormation masks the response
+    /// data when it is retrieved through `Fn::GetAtt`, not set by default.
+    pub fn no_echo(mut self, no_echo: bool) -> Provider<T> {
+        self.no_echo = no_echo;
+        self
+    }
+
+    /// Splits the provider into its strategy and the responder sending the responses as
+    /// configured, for the ways of processing requests implemented elsewhere.
+    pub(crate) fn into_parts(self) -> (T, Responder) {
+        let responder = Responder {
+            http_client: self.http_client,
+            response_url_policy: self.response_url_policy.map(Arc::new),
+            redactor: self.redactor.map(Arc::new),
+            reason_format: Arc::new(self.reason_format),
+            metrics: self.metrics,
+            no_echo: self.no_echo,
+        };
+        (self.strategy, responder)
+    }
+
+    /// Process AWS CloudFormation custom resource requests with this configuration, as
+    /// [`process`] would.
+    ///
+    /// [`process`]: fn.process.html
+    pub fn process<F, R, P, S>(
+        self,
+        f: F,
+    ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+        R::Future: Send,
+        S: Serialize + Send + 'static,
+        P: Send + 'static,
+    {
+        self.process_with(|_request| SensitiveValues::new(), f)
+    }
+
+    /// Process AWS CloudFormation custom resource requests with this configuration, additionally
+    /// redacting the values of the [`SensitiveProperties`] from the failure reasons.
+    ///
+    /// The values are redacted even if no [`redactor`] is configured.
+    ///
+    /// [`SensitiveProperties`]: trait.SensitiveProperties.html
+    /// [`redactor`]: #method.redactor
+    pub fn process_sensitive<F, R, P, S>(
+        self,
+        f: F,
+    ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+        R::Future: Send,
+        S: Serialize + Send + 'static,
+        P: SensitiveProperties + Send + 'static,
+    {
+        self.process_with(CfnRequest::sensitive_values, f)
+    }
+
+    fn process_with<V, F, R, P, S>(
+        self,
+        sensitive_values: V,
+        f: F,
+    ) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        T: PhysicalIdStrategy<P> + Send + Sync + 'static,
+        V: Fn(&CfnRequest<P>) -> SensitiveValues + Send + Sync + 'static,
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+        R::Future: Send,
+        S: Serialize + Send + 'static,
+        P: Send + 'static,
+    {
+        let (strategy, responder) = self.into_parts();
+
+        // The process below is a bit convoluted to read, the main reason for this is the
+        // following: we want to forward the response given by the closure `f` to our caller, while
+        // using that same response to inform AWS CloudFormation of the status of the custom
+        // resource.
+        //
+        // To accomplish this, we use a nested chain of futures that works as follows.
+        //
+        // 1. Check that the response may be sent to the response URL, before doing anything on
+        //    behalf of the request.
+        // 2. Extract the fields required for the AWS CloudFormation response from the request,
+        //    such that the request itself can be moved into `f` without being cloned.
+        // 3. Call closure `f`.
+        // 4. Transform the extracted fields into a AWS CloudFormation response, deciding on
+        //    success or failure through the result returned by `f`.
+        // 5. Try to serialize and send the response to AWS CloudFormation (if this fails at any
+        //    step, propagate the error through to our caller).
+        // 6. If informing AWS CloudFormation succeeded, return the initial result of `f` to our
+        //    caller.
+        move |request: CfnRequest<P>| {
+            let span = request_span(&request);
+            let _entered = span.enter();
+            log_request(&request);
+            if let Err(e) = responder.check(request.response_url()) {
+                return Box::new(future::err(e));
+            }
+            let response_url = request.response_url().to_owned();
+            let physical_resource_id = request.physical_resource_id_with(&strategy);
+            let response_fields = ResponseFields::new(&request, physical_resource_id);
+            let measured = responder.measure(&request);
+
+            let responder = responder.clone();
+            let sensitive_values = sensitive_values(&request);
+
+            let handler = {
+                let handler_span = info_span!("handler");
+                let _entered = handler_span.enter();
+                f(request).into_future().instrument(handler_span.clone())
+            };
+            let processed = handler.then(move |request_result| {
+                let cfn_response =
+                    responder.response(response_fields, &request_result, &sensitive_values);
+                responder
+                    .send(response_url, &cfn_response, measured)
+                    .then(move |sent| sent.and(request_result))
+            });
+            Box::new(processed.instrument(span.clone()))
+        }
+    }
+}
+
+/// Sends the responses to AWS CloudFormation as configured through a [`Provider`], shared by all
+/// the ways of processing requests.
+///
+/// [`Provider`]: struct.Provider.html
+#[derive(Debug, Clone)]
+pub(crate) struct Responder {
+    http_client: HttpClient,
+    response_url_policy: Option<Arc<ResponseUrlPolicy>>,
+    redactor: Option<Arc<Redactor>>,
+    reason_format: Arc<ReasonFormat>,
+    metrics: Option<Metrics>,
+    no_echo: bool,
+}
+
+/// What the metrics of a request are recorded with, captured before the request is handled.
+pub(crate) struct Measured {
+    metrics: Metrics,
+    resource_type: String,
+    request_id: String,
+    request_type: RequestType,
+    started: Instant,
+}
+
+impl Responder {
+    /// Checks whether responses may be sent to the given response URL, logging the refusal if
+    /// not. This happens before anything is done on behalf of the request.
+    pub(crate) fn check(&self, response_url: &str) -> Result<(), Error> {
+        match self.response_url_policy {
+            Some(ref policy) => policy.check(response_url).map_err(|e| {
+                error!(error = %e, "refusing to respond to the response URL");
+                e
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts measuring the request, if metrics are emitted. Call this right before handling the
+    /// request.
+    pub(crate) fn measure<P>(&self, request: &CfnRequest<P>) -> Option<Measured> {
+        self.metrics.clone().map(|metrics| Measured {
+            metrics,
+            resource_type: request.resource_type().to_owned(),
+            request_id: request.request_id().to_owned(),
+            request_type: request.request_type(),
+            started: Instant::now(),
+        })
+    }
+
+    /// Turns the result into the response, deriving the failure reason as configured and
+    /// redacting it. The given sensitive values are redacted even if no redactor is configured.
+    /// `NoEcho` is set if configured.
+    pub(crate) fn response<S>(
+        &self,
+        response_fields: ResponseFields,
+        result: &Result<Option<S>, Error>,
+        sensitive_values: &SensitiveValues,
+    ) -> CfnResponse
+    where
+        S: Serialize,
+    {
+        let response_fields = response_fields.no_echo(self.no_echo);
+        response_fields.into_response_with(result, |error| {
+            self.reason_format
+                .format_redacted(error, |reason| match self.redactor {
+                    Some(ref redactor) => redactor.redact(&reason, sensitive_values),
+                    None if !sensitive_values.is_empty() => {
+                        Redactor::new().redact(&reason, sensitive_values)
+                    }
+                    None => reason,
+                })
+        })
+    }
+
+    /// Sends the response to the given response URL, if the policy allows it, recording the
+    /// metrics of the measured request afterwards.
+    pub(crate) fn send(
+        &self,
+        response_url: String,
+        cfn_response: &CfnResponse,
+        measured: Option<Measured>,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let status = cfn_response.status();
+        let response_started = Instant::now();
+        let sent = match self.check(&response_url) {
+            Ok(()) => Either::A(send_response(&self.http_client, response_url, cfn_response)),
+            Err(e) => Either::B(future::err(e)),
+        };
+        sent.then(move |sent| {
+            if let Some(measured) = measured {
+                measured.metrics.record(&Measurement {
+                    resource_type: measured.resource_type,
+                    request_id: measured.request_id,
+                    request_type: measured.request_type,
+                    status,
+                    handler_duration: response_started.duration_since(measured.started),
+                    response_duration: response_started.elapsed(),
+                });
+            }
+            sent
+        })
+    }
+}
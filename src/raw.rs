@@ -0,0 +1,236 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use futures::future;
+use futures::{Future, IntoFuture};
+use provider::Responder;
+use serde::de::{Deserialize, DeserializeOwned};
+use serde_json::{self, Value};
+use serde_path_to_error;
+use {CfnRequest, Provider, ResponseFields, SensitiveValues};
+
+/// Process an AWS CloudFormation custom resource request given as raw JSON.
+///
+/// If the request cannot be deserialized into a [`CfnRequest<P>`], e.g. because a property has an
+/// unexpected type or a required property is missing, the AWS Lambda runtime would usually fail
+/// before your code even runs. No response would be sent to AWS CloudFormation, leaving the stack
+/// waiting for its timeout. This function instead extracts just the fields required to respond
+/// from the raw JSON and informs AWS CloudFormation about the failure, stating the path of the
+/// offending property as part of the reason (e.g. `ResourceProperties.Rules[1].Port`). The same
+/// reason is returned to the caller as an error.
+///
+/// Delete requests are the exception: failing them would leave the stack unable to delete the
+/// custom resource, e.g. after a failed creation with invalid properties or a change to the
+/// properties' type. Instead, the error is logged and AWS CloudFormation is informed about the
+/// successful deletion, echoing the physical resource ID, while the caller receives `Ok(None)`.
+///
+/// If the request can be deserialized, it is passed to `f`, which is expected to inform AWS
+/// CloudFormation itself. This makes `f` usually the function returned by [`process`] or one of its
+/// siblings.
+///
+/// ## Example
+///
+/// ```no_run
+/// # extern crate aws_lambda as lambda;
+/// # extern crate cfn_resource_provider;
+/// # extern crate serde_json;
+/// # use cfn_resource_provider::*;
+/// fn main() {
+///     lambda::start(process_json(process(|event: CfnRequest<Ignored>| {
+///         // Only invoked if the request could be deserialized.
+///         Ok(None::<()>)
+///     })));
+/// }
+/// ```
+///
+/// Use [`Provider::process_json`] to configure how the failure is responded with.
+///
+/// [`CfnRequest<P>`]: enum.CfnRequest.html
+/// [`process`]: fn.process.html
+/// [`Provider::process_json`]: struct.Provider.html#method.process_json
+pub fn process_json<F, R, P, S>(
+    f: F,
+) -> impl Fn(Value) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + 'static,
+    R::Future: Send,
+    P: DeserializeOwned,
+    S: Send + 'static,
+{
+    Provider::new().process_json(f)
+}
+
+impl<T> Provider<T> {
+    /// Process AWS CloudFormation custom resource requests given as raw JSON with this
+    /// configuration, as [`process_json`] would.
+    ///
+    /// The configuration applies to the failure responses sent for requests that cannot be
+    /// deserialized, `f` is expected to be configured on its own.
+    ///
+    /// [`process_json`]: fn.process_json.html
+    pub fn process_json<F, R, P, S>(
+        self,
+        f: F,
+    ) -> impl Fn(Value) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+    where
+        F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + 'static,
+        R::Future: Send,
+        P: DeserializeOwned,
+        S: Send + 'static,
+    {
+        let (_, responder) = self.into_parts();
+        move |request: Value| match CfnRequest::<P>::deserialize(&request) {
+            Ok(request) => Box::new(f(request).into_future()),
+            Err(e) => {
+                let reason = format!(
+                    "failed to deserialize the request: {}",
+                    deserialization_error::<P>(&request, &e)
+                );
+                respond_malformed(&responder, &request, reason)
+            }
+        }
+    }
+}
+
+/// Informs AWS CloudFormation that the raw request could not be processed, using only the fields
+/// of the request required to respond. The reason is returned to the caller as an error as well.
+///
+/// Delete requests are responded to with a success instead, only logging the reason, such that the
+/// stack is not stuck with a resource it cannot delete.
+pub(crate) fn respond_malformed<S>(
+    responder: &Responder,
+    request: &Value,
+    reason: String,
+) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    S: Send + 'static,
+{
+    let response_url = match field(request, "ResponseURL") {
+        Some(response_url) => response_url,
+        None => {
+            return Box::new(future::err(format_err!(
+                "{}, unable to respond without a `ResponseURL`",
+                reason
+            )))
+        }
+    };
+    let response_fields = ResponseFields {
+        request_id: field(request, "RequestId").unwrap_or_default(),
+        logical_resource_id: field(request, "LogicalResourceId").unwrap_or_default(),
+        stack_id: field(request, "StackId").unwrap_or_default(),
+        // AWS CloudFormation requires a physical resource ID even for failed creations, for
+        // which we fall back to the logical resource ID.
+        physical_resource_id: field(request, "PhysicalResourceId")
+            .or_else(|| field(request, "LogicalResourceId"))
+            .unwrap_or_default(),
+        no_echo: None,
+    };
+    let result = if request.get("RequestType").and_then(Value::as_str) == Some("Delete") {
+        error!(
+            reason = %reason,
+            "failed to process the Delete request, responding with success to not block the stack"
+        );
+        Ok(None)
+    } else {
+        Err(format_err!("{}", reason))
+    };
+    let cfn_response = responder.response::<()>(response_fields, &result, &SensitiveValues::new());
+    Box::new(
+        responder
+            .send(response_url, &cfn_response, None)
+            .and_then(move |_| result.map(|_| None)),
+    )
+}
+
+/// Retrieves a string field from the raw request.
+fn field(request: &Value, name: &str) -> Option<String> {
+    request.get(name).and_then(Value::as_str).map(str::to_owned)
+}
+
+/// Describes why deserializing the request failed. If the resource properties are at fault, the
+/// description contains the path of the offending property.
+pub(crate) fn deserialization_error<P>(request: &Value, error: &serde_json::Error) -> String
+where
+    P: DeserializeOwned,
+{
+    for name in &["ResourceProperties", "OldResourceProperties"] {
+        let properties = match request.get(name) {
+            Some(properties) => properties,
+            None => continue,
+        };
+        if let Err(e) = serde_path_to_error::deserialize::<_, P>(properties) {
+            let path = e.path().to_string();
+            return if path == "." {
+                format!("{}: {}", name, e.inner())
+            } else {
+                format!("{}.{}: {}", name, path, e.inner())
+            };
+        }
+    }
+    error.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Rule {
+        #[allow(dead_code)]
+        port: u16,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Properties {
+        #[allow(dead_code)]
+        rules: Vec<Rule>,
+    }
+
+    fn request(resource_properties: Value) -> Value {
+        json!({
+            "RequestType": "Create",
+            "RequestId": "unique id for this create request",
+            "ResponseURL": "http://pre-signed-S3-url-for-response",
+            "ResourceType": "Custom::MyCustomResourceType",
+            "LogicalResourceId": "MyCustomResource",
+            "StackId": "arn:aws:cloudformation:us-east-1:123456789012:stack/stack-name/guid",
+            "ResourceProperties": resource_properties,
+        })
+    }
+
+    fn describe(request: &Value) -> String {
+        let error = CfnRequest::<Properties>::deserialize(request).unwrap_err();
+        deserialization_error::<Properties>(request, &error)
+    }
+
+    #[test]
+    fn error_contains_property_path() {
+        let error = describe(&request(
+            json!({ "Rules": [{ "Port": 22 }, { "Port": "ssh" }] }),
+        ));
+        assert!(error.starts_with("ResourceProperties.Rules[1].Port: invalid type"));
+    }
+
+    #[test]
+    fn error_for_missing_property() {
+        let error = describe(&request(json!({})));
+        assert_eq!(error, "ResourceProperties: missing field `Rules`");
+    }
+
+    #[test]
+    fn error_outside_of_properties() {
+        let mut request = request(json!({ "Rules": [] }));
+        request["RequestType"] = json!("Replace");
+        assert!(describe(&request).starts_with("unknown variant `Replace`"));
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use std::env;
+
+/// What is appended to a truncated reason.
+const ELLIPSIS: &str = "...";
+
+/// The environment variable AWS Lambda sets to the log group of the function.
+const LOG_GROUP_VARIABLE: &str = "AWS_LAMBDA_LOG_GROUP_NAME";
+
+/// The environment variable AWS Lambda sets to the log stream of the current instance.
+const LOG_STREAM_VARIABLE: &str = "AWS_LAMBDA_LOG_STREAM_NAME";
+
+/// Describes how the failure reason sent to AWS CloudFormation is derived from an error.
+///
+/// By default, the reason is the message of the error itself, just as its `Display`
+/// implementation renders it. Errors are often wrapped in context along the way though, in which
+/// case the top-level message does not say much on its own, e.g. "failed to create the bucket".
+/// Including the [`cause_chain`] turns this into "failed to create the bucket: access denied".
+///
+/// The whole response sent to AWS CloudFormation must not exceed 4096 bytes, and the console only
+/// shows the beginning of long reasons. A [`max_length`] truncates longer reasons, ending them with
+/// an ellipsis.
+///
+/// As the reason can only tell so much, the [`log_stream`] option points to the Amazon CloudWatch
+/// Logs stream the AWS Lambda function writes to, where the details can be found. The pointer is
+/// never truncated, the reason is shortened instead.
+///
+/// Use [`Provider::reason_format`] or [`CfnRequest::into_response_with`] to apply a format.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # extern crate failure;
+/// # use cfn_resource_provider::*;
+/// # use failure::ResultExt;
+/// # fn main() {
+/// let error = Err::<(), _>(failure::err_msg("access denied"))
+///     .context("failed to create the bucket")
+///     .unwrap_err()
+///     .into();
+///
+/// assert_eq!(ReasonFormat::new().format(&error), "failed to create the bucket");
+/// assert_eq!(
+///     ReasonFormat::new().cause_chain().format(&error),
+///     "failed to create the bucket: access denied",
+/// );
+/// assert_eq!(
+///     ReasonFormat::new().cause_chain().max_length(20).format(&error),
+///     "failed to create ...",
+/// );
+/// # }
+/// ```
+///
+/// [`cause_chain`]: #method.cause_chain
+/// [`max_length`]: #method.max_length
+/// [`log_stream`]: #method.log_stream
+/// [`Provider::reason_format`]: struct.Provider.html#method.reason_format
+/// [`CfnRequest::into_response_with`]: enum.CfnRequest.html#method.into_response_with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReasonFormat {
+    cause_chain: bool,
+    max_length: Option<usize>,
+    log_stream: bool,
+}
+
+impl ReasonFormat {
+    /// Creates a format using only the message of the error itself, without a length limit.
+    pub fn new() -> ReasonFormat {
+        ReasonFormat::default()
+    }
+
+    /// Includes the messages of all causes of the error, separated by `: `. Causes whose message
+    /// is already part of the message before them are left out.
+    pub fn cause_chain(mut self) -> ReasonFormat {
+        self.cause_chain = true;
+        self
+    }
+
+    /// Truncates reasons longer than the given number of bytes, including the ellipsis appended.
+    pub fn max_length(mut self, max_length: usize) -> ReasonFormat {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Appends "(See CloudWatch log stream <group>/<stream>)" to the reason, as read from the
+    /// `AWS_LAMBDA_LOG_GROUP_NAME` and `AWS_LAMBDA_LOG_STREAM_NAME` environment variables AWS
+    /// Lambda sets. Nothing is appended if either of them is not set.
+    pub fn log_stream(mut self) -> ReasonFormat {
+        self.log_stream = true;
+        self
+    }
+
+    /// Derives the failure reason from the error.
+    pub fn format(&self, error: &Error) -> String {
+        self.format_redacted(error, |reason| reason)
+    }
+
+    /// Derives the failure reason from the error, passing it through `redact` before it is
+    /// truncated and the log stream is appended.
+    pub(crate) fn format_redacted<R>(&self, error: &Error, redact: R) -> String
+    where
+        R: FnOnce(String) -> String,
+    {
+        let log_stream = if self.log_stream {
+            log_stream(|name| env::var(name).ok())
+        } else {
+            None
+        };
+        self.finish(redact(self.describe(error)), log_stream)
+    }
+
+    fn describe(&self, error: &Error) -> String {
+        if self.cause_chain {
+            let mut reason = String::new();
+            let mut previous = String::new();
+            for cause in error.iter_chain() {
+                let message = cause.to_string();
+                if message.is_empty() || previous.contains(&message) {
+                    continue;
+                }
+                if !reason.is_empty() {
+                    reason.push_str(": ");
+                }
+                reason.push_str(&message);
+                previous = message;
+            }
+            reason
+        } else {
+            error.to_string()
+        }
+    }
+
+    /// Appends the log stream, if any, truncating the reason such that the result does not exceed
+    /// the maximum length.
+    fn finish(&self, reason: String, log_stream: Option<String>) -> String {
+        let pointer = match log_stream {
+            Some(log_stream) => format!(" (See CloudWatch log stream {})", log_stream),
+            None => String::new(),
+        };
+        match self.max_length {
+            Some(max_length) if pointer.len() < max_length => {
+                truncate(reason, max_length - pointer.len()) + &pointer
+            }
+            Some(max_length) => truncate(reason + &pointer, max_length),
+            None => reason + &pointer,
+        }
+    }
+}
+
+/// The log group and stream of the AWS Lambda function, as set in its environment, which is read
+/// through `lookup`.
+fn log_stream<L>(lookup: L) -> Option<String>
+where
+    L: Fn(&str) -> Option<String>,
+{
+    let variable = |name| lookup(name).filter(|value: &String| !value.is_empty());
+    let group = variable(LOG_GROUP_VARIABLE)?;
+    let stream = variable(LOG_STREAM_VARIABLE)?;
+    Some(format!("{}/{}", group, stream))
+}
+
+/// Truncates the reason to the maximum length, ending it with an ellipsis. If not even the
+/// ellipsis fits, as much of it as fits is returned.
+fn truncate(mut reason: String, max_length: usize) -> String {
+    if reason.len() <= max_length {
+        return reason;
+    }
+    if max_length < ELLIPSIS.len() {
+        return ELLIPSIS[..max_length].to_owned();
+    }
+    let mut end = max_length - ELLIPSIS.len();
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    reason.truncate(end);
+    reason.push_str(ELLIPSIS);
+    reason
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use failure::{err_msg, ResultExt};
+
+    fn error() -> Error {
+        Err::<(), _>(err_msg("access denied"))
+            .context("failed to put the bucket policy")
+            .context("failed to create the bucket")
+            .unwrap_err()
+            .into()
+    }
+
+    #[test]
+    fn top_level_message() {
+        assert_eq!(
+            ReasonFormat::new().format(&error()),
+            "failed to create the bucket"
+        );
+    }
+
+    #[test]
+    fn cause_chain() {
+        assert_eq!(
+            ReasonFormat::new().cause_chain().format(&error()),
+            "failed to create the bucket: failed to put the bucket policy: access denied"
+        );
+    }
+
+    #[test]
+    fn cause_chain_skips_repeated_messages() {
+        let error: Error = Err::<(), _>(err_msg("access denied"))
+            .with_context(|e| format!("failed to create the bucket: {}", e))
+            .unwrap_err()
+            .into();
+        assert_eq!(
+            ReasonFormat::new().cause_chain().format(&error),
+            "failed to create the bucket: access denied"
+        );
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncate("short".to_owned(), 10), "short");
+        assert_eq!(truncate("exactly 10".to_owned(), 10), "exactly 10");
+        assert_eq!(truncate("a bit too long".to_owned(), 10), "a bit t...");
+        // Multi-byte characters are never split.
+        assert_eq!(truncate("ääääääää".to_owned(), 10), "äää...");
+        // The limit holds even if not even the ellipsis fits.
+        assert_eq!(truncate("a bit too long".to_owned(), 3), "...");
+        assert_eq!(truncate("a bit too long".to_owned(), 2), "..");
+        assert_eq!(truncate("a bit too long".to_owned(), 0), "");
+    }
+
+    #[test]
+    fn log_stream_is_kept_when_truncating() {
+        let log_stream = Some("/aws/lambda/fn/stream".to_owned());
+        let format = ReasonFormat::new().log_stream();
+        assert_eq!(
+            format.finish("access denied".to_owned(), log_stream.clone()),
+            "access denied (See CloudWatch log stream /aws/lambda/fn/stream)"
+        );
+        assert_eq!(
+            format
+                .clone()
+                .max_length(60)
+                .finish("access denied".to_owned(), log_stream.clone()),
+            "access ... (See CloudWatch log stream /aws/lambda/fn/stream)"
+        );
+        // If not even the log stream fits, the whole reason is truncated.
+        assert_eq!(
+            format
+                .max_length(20)
+                .finish("access denied".to_owned(), log_stream),
+            "access denied (Se..."
+        );
+    }
+
+    #[test]
+    fn log_stream_from_environment() {
+        let environment = |stream: &'static str| {
+            move |name: &str| match name {
+                LOG_GROUP_VARIABLE => Some("/aws/lambda/custom-resource".to_owned()),
+                LOG_STREAM_VARIABLE => Some(stream.to_owned()),
+                _ => None,
+            }
+        };
+        assert_eq!(
+            log_stream(environment("2020/01/01/[$LATEST]0123456789abcdef")),
+            Some("/aws/lambda/custom-resource/2020/01/01/[$LATEST]0123456789abcdef".to_owned())
+        );
+        assert_eq!(log_stream(environment("")), None);
+        assert_eq!(log_stream(|_: &str| None), None);
+    }
+}
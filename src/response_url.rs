@@ -0,0 +1,241 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use reqwest::Url;
+
+/// The hosts of the pre-signed Amazon S3 URLs AWS CloudFormation sends as `ResponseURL`, covering
+/// virtual-hosted and path-style URLs in the global, regional, dual-stack and China endpoints.
+const S3_HOSTS: &[&str] = &[
+    "*.s3.amazonaws.com",
+    "*.s3-*.amazonaws.com",
+    "*.s3.*.amazonaws.com",
+    "*.s3.*.amazonaws.com.cn",
+    "*.s3.dualstack.*.amazonaws.com",
+    "*.s3.dualstack.*.amazonaws.com.cn",
+    "s3.amazonaws.com",
+    "s3-*.amazonaws.com",
+    "s3.*.amazonaws.com",
+    "s3.*.amazonaws.com.cn",
+    "s3.dualstack.*.amazonaws.com",
+    "s3.dualstack.*.amazonaws.com.cn",
+];
+
+/// A policy deciding which response URLs responses may be sent to.
+///
+/// The response, including its data, is sent to whatever `ResponseURL` the request contains.
+/// Anyone able to invoke your AWS Lambda function could thus make it send the data elsewhere. With
+/// a policy configured through [`Provider::response_url_policy`], responses are only sent to URLs
+/// using HTTPS whose host matches one of the allowed host patterns. Responses to any other URL
+/// are refused, failing the processing of the request.
+///
+/// Host patterns are matched label by label, where a `*` matches any part of a single label, e.g.
+/// `*.s3-*.amazonaws.com` matches `bucket.s3-us-west-2.amazonaws.com`.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # use cfn_resource_provider::*;
+/// # fn main() {
+/// let processor = Provider::new()
+///     .response_url_policy(ResponseUrlPolicy::s3())
+///     .process(|event: CfnRequest<Ignored>| Ok(None::<()>));
+///
+/// // For local testing, allow plain HTTP to a local server.
+/// let local = ResponseUrlPolicy::s3().allow_http().allow_host("localhost");
+/// assert!(local.check("http://localhost:8080/response").is_ok());
+/// # }
+/// ```
+///
+/// [`Provider::response_url_policy`]: struct.Provider.html#method.response_url_policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseUrlPolicy {
+    allow_http: bool,
+    hosts: Vec<String>,
+}
+
+impl ResponseUrlPolicy {
+    /// Creates a policy only allowing the pre-signed Amazon S3 URLs AWS CloudFormation uses, over
+    /// HTTPS.
+    pub fn s3() -> ResponseUrlPolicy {
+        ResponseUrlPolicy {
+            allow_http: false,
+            hosts: S3_HOSTS.iter().map(|host| (*host).to_owned()).collect(),
+        }
+    }
+
+    /// Creates a policy allowing no host at all, to be extended through [`allow_host`].
+    ///
+    /// [`allow_host`]: #method.allow_host
+    pub fn empty() -> ResponseUrlPolicy {
+        ResponseUrlPolicy {
+            allow_http: false,
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Additionally allows hosts matching the given pattern.
+    pub fn allow_host<H>(mut self, pattern: H) -> ResponseUrlPolicy
+    where
+        H: Into<String>,
+    {
+        self.hosts.push(pattern.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Additionally allows plain HTTP, which should only be used for local testing.
+    pub fn allow_http(mut self) -> ResponseUrlPolicy {
+        self.allow_http = true;
+        self
+    }
+
+    /// Checks whether responses may be sent to the given URL, describing why not otherwise.
+    ///
+    /// The description contains the host of the URL, but never the full URL, as the signature of
+    /// a pre-signed URL grants access to the response object.
+    pub fn check(&self, response_url: &str) -> Result<(), Error> {
+        let url = Url::parse(response_url)
+            .map_err(|e| format_err!("the response URL is not a valid URL: {}", e))?;
+        match url.scheme() {
+            "https" => {}
+            "http" if self.allow_http => {}
+            scheme => bail!(
+                "the response URL uses the scheme `{}`, but only HTTPS is allowed",
+                scheme
+            ),
+        }
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => bail!("the response URL does not contain a host"),
+        };
+        if self
+            .hosts
+            .iter()
+            .any(|pattern| host_matches(pattern, &host))
+        {
+            Ok(())
+        } else {
+            bail!("the host `{}` of the response URL is not allowed", host)
+        }
+    }
+}
+
+impl Default for ResponseUrlPolicy {
+    fn default() -> ResponseUrlPolicy {
+        ResponseUrlPolicy::s3()
+    }
+}
+
+/// Whether the host matches the pattern, label by label.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let patterns: Vec<&str> = pattern.split('.').collect();
+    let labels: Vec<&str> = host.split('.').collect();
+    patterns.len() == labels.len()
+        && patterns
+            .iter()
+            .zip(labels)
+            .all(|(pattern, label)| label_matches(pattern, label))
+}
+
+/// Whether the label matches the pattern, in which a `*` matches any, non-empty part of the label.
+fn label_matches(pattern: &str, label: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == label,
+        Some(index) => {
+            let (prefix, rest) = (&pattern[..index], &pattern[index + 1..]);
+            label.starts_with(prefix)
+                && (prefix.len() + 1..=label.len())
+                    .filter(|end| label.is_char_boundary(*end))
+                    .any(|end| label_matches(rest, &label[end..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn s3_urls_are_allowed() {
+        let policy = ResponseUrlPolicy::s3();
+        for url in &[
+            "https://cloudformation-custom-resource-response-useast1.s3.amazonaws.com/arn%3Aaws?X-Amz-Signature=abc",
+            "https://cloudformation-custom-resource-response-uswest2.s3-us-west-2.amazonaws.com/arn",
+            "https://cloudformation-custom-resource-response-eucentral1.s3.eu-central-1.amazonaws.com/arn",
+            "https://cloudformation-custom-resource-response-cnnorth1.s3.cn-north-1.amazonaws.com.cn/arn",
+            "https://s3.amazonaws.com/cloudformation-custom-resource-response-useast1/arn",
+            "https://S3-US-WEST-2.amazonaws.com/cloudformation-custom-resource-response-uswest2/arn",
+        ] {
+            assert!(policy.check(url).is_ok(), "{} is not allowed", url);
+        }
+    }
+
+    #[test]
+    fn s3_dualstack_urls_are_allowed() {
+        let policy = ResponseUrlPolicy::s3();
+        for url in &[
+            "https://cloudformation-custom-resource-response-useast1.s3.dualstack.us-east-1.amazonaws.com/arn",
+            "https://cloudformation-custom-resource-response-cnnorth1.s3.dualstack.cn-north-1.amazonaws.com.cn/arn",
+            "https://s3.dualstack.us-east-1.amazonaws.com/cloudformation-custom-resource-response-useast1/arn",
+            "https://s3.dualstack.cn-north-1.amazonaws.com.cn/cloudformation-custom-resource-response-cnnorth1/arn",
+        ] {
+            assert!(policy.check(url).is_ok(), "{} is not allowed", url);
+        }
+    }
+
+    #[test]
+    fn other_urls_are_refused() {
+        let policy = ResponseUrlPolicy::s3();
+        for url in &[
+            "http://bucket.s3.amazonaws.com/arn",
+            "https://example.com/arn",
+            "https://bucket.s3.amazonaws.com.example.com/arn",
+            "https://s3.amazonaws.com.example.com/arn",
+            "https://bucket.s3-.amazonaws.com/arn",
+            "https://a.b.s3.us-east-1.amazonaws.com/arn",
+            "file:///etc/passwd",
+            "not a url",
+        ] {
+            assert!(policy.check(url).is_err(), "{} is allowed", url);
+        }
+    }
+
+    #[test]
+    fn error_does_not_contain_url() {
+        let error = ResponseUrlPolicy::s3()
+            .check("https://example.com/arn?X-Amz-Signature=secret")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the host `example.com` of the response URL is not allowed"
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let policy = ResponseUrlPolicy::empty().allow_host("127.0.0.1");
+        assert!(policy.check("https://127.0.0.1:8080/response").is_ok());
+        assert!(policy.check("http://127.0.0.1:8080/response").is_err());
+        assert!(policy
+            .allow_http()
+            .check("http://127.0.0.1:8080/response")
+            .is_ok());
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(label_matches("s3-*", "s3-us-west-2"));
+        assert!(!label_matches("s3-*", "s3-"));
+        assert!(label_matches("*", "bucket"));
+        assert!(label_matches("a*c*e", "abcde"));
+        assert!(!label_matches("a*c*e", "abcd"));
+        assert!(host_matches("*.example.com", "www.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject};
+use schemars::JsonSchema;
+
+/// The name of the property every custom resource has to specify, holding the ARN of the AWS
+/// Lambda function or Amazon SNS topic backing the custom resource.
+const SERVICE_TOKEN: &str = "ServiceToken";
+
+/// Generates the JSON Schema for the resource properties `P` used in `CfnRequest<P>`.
+///
+/// The schema describes the `Properties` of the custom resource as specified in an AWS
+/// CloudFormation template, which is why the `ServiceToken` property AWS CloudFormation requires
+/// is added to it as a required string property. (This only happens if the schema of `P`
+/// describes an object, which is the case for all structs.) You can publish the schema alongside
+/// your AWS Lambda function, lint templates against it or render documentation from it.
+///
+/// This function is only available with the `schemars` feature enabled. The resource properties
+/// have to implement [`JsonSchema`], which is usually done by deriving it alongside
+/// `Deserialize`. Keep in mind that the schema only reflects what [schemars] knows about your
+/// type: if you deserialize your properties using `#[custom_resource]`, you have to tell it about
+/// the `PascalCase` property names, e.g. using `#[serde(rename_all = "PascalCase")]`.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # #[macro_use]
+/// # extern crate schemars;
+/// # #[macro_use]
+/// # extern crate serde;
+/// # extern crate serde_json;
+/// # use cfn_resource_provider::*;
+/// #[derive(Deserialize, JsonSchema)]
+/// #[serde(rename_all = "PascalCase")]
+/// struct MyResourceProperties {
+///     bucket_name: String,
+///     ttl: Option<u32>,
+/// }
+///
+/// # fn main() {
+/// let schema = resource_properties_schema::<MyResourceProperties>();
+/// println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+/// # }
+/// ```
+///
+/// [`JsonSchema`]: https://docs.rs/schemars/0.8/schemars/trait.JsonSchema.html
+/// [schemars]: https://docs.rs/schemars/0.8
+pub fn resource_properties_schema<P>() -> RootSchema
+where
+    P: JsonSchema,
+{
+    let mut generator = SchemaGenerator::default();
+    let mut root = generator.root_schema_for::<P>();
+    if let Some(ref mut object) = root.schema.object {
+        object.properties.insert(
+            SERVICE_TOKEN.to_owned(),
+            Schema::Object(SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                ..SchemaObject::default()
+            }),
+        );
+        object.required.insert(SERVICE_TOKEN.to_owned());
+    }
+    root
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    #[serde(rename_all = "PascalCase")]
+    struct Properties {
+        bucket_name: String,
+        ttl: Option<u32>,
+    }
+
+    #[test]
+    fn schema_contains_properties_and_service_token() {
+        let schema = serde_json::to_value(resource_properties_schema::<Properties>()).unwrap();
+        assert_eq!(schema["title"], "Properties");
+        assert_eq!(schema["properties"]["BucketName"]["type"], "string");
+        assert_eq!(schema["properties"]["ServiceToken"]["type"], "string");
+        assert!(schema["properties"]["Ttl"].is_object());
+
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&Value::from("BucketName")));
+        assert!(required.contains(&Value::from("ServiceToken")));
+        assert!(!required.contains(&Value::from("Ttl")));
+    }
+
+    #[test]
+    fn non_object_schema_is_left_alone() {
+        let schema = serde_json::to_value(resource_properties_schema::<String>()).unwrap();
+        assert_eq!(schema["type"], "string");
+        assert!(schema.get("properties").is_none());
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The parsed form of the Amazon Resource Name (ARN) that identifies an AWS CloudFormation stack,
+/// as provided in the `StackId` field of every [`CfnRequest`].
+///
+/// A stack ARN has the following format:
+///
+/// ```text
+/// arn:{partition}:cloudformation:{region}:{account}:stack/{stack_name}/{guid}
+/// ```
+///
+/// You can retrieve the parsed stack ID of a request through [`CfnRequest::stack_arn`], or parse
+/// any string through [`FromStr`].
+///
+/// ## Example
+///
+/// ```
+/// # use cfn_resource_provider::*;
+/// let stack_arn: StackArn = "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(stack_arn.partition(), "aws");
+/// assert_eq!(stack_arn.region(), "us-east-2");
+/// assert_eq!(stack_arn.account(), "123456789012");
+/// assert_eq!(stack_arn.stack_name(), "stack-name");
+/// assert_eq!(stack_arn.guid(), "guid");
+/// ```
+///
+/// [`CfnRequest`]: enum.CfnRequest.html
+/// [`CfnRequest::stack_arn`]: enum.CfnRequest.html#method.stack_arn
+/// [`FromStr`]: https://doc.rust-lang.org/std/str/trait.FromStr.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StackArn {
+    partition: String,
+    region: String,
+    account: String,
+    stack_name: String,
+    guid: String,
+}
+
+impl StackArn {
+    /// The partition the stack resides in, e.g. `aws` or `aws-cn`.
+    pub fn partition(&self) -> &str {
+        &self.partition
+    }
+
+    /// The region the stack resides in, e.g. `us-east-1`.
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// The ID of the AWS account owning the stack.
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// The name of the stack.
+    pub fn stack_name(&self) -> &str {
+        &self.stack_name
+    }
+
+    /// The globally unique identifier AWS CloudFormation assigned to the stack.
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+}
+
+impl FromStr for StackArn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<StackArn, Error> {
+        let mut parts = s.splitn(6, ':');
+        let (arn, partition, service, region, account, resource) = match (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) {
+            (
+                Some(arn),
+                Some(partition),
+                Some(service),
+                Some(region),
+                Some(account),
+                Some(resource),
+            ) => (arn, partition, service, region, account, resource),
+            _ => bail!(
+                "malformed stack ID `{}`: expected six colon-separated parts",
+                s
+            ),
+        };
+        if arn != "arn" {
+            bail!(
+                "malformed stack ID `{}`: expected it to start with `arn`",
+                s
+            );
+        }
+        if service != "cloudformation" {
+            bail!(
+                "malformed stack ID `{}`: expected service `cloudformation`",
+                s
+            );
+        }
+
+        let mut resource_parts = resource.splitn(3, '/');
+        let (stack_name, guid) = match (
+            resource_parts.next(),
+            resource_parts.next(),
+            resource_parts.next(),
+        ) {
+            (Some("stack"), Some(stack_name), Some(guid)) => (stack_name, guid),
+            _ => bail!(
+                "malformed stack ID `{}`: expected resource of the form `stack/{{stack_name}}/{{guid}}`",
+                s
+            ),
+        };
+
+        if [partition, region, account, stack_name, guid]
+            .iter()
+            .any(|part| part.is_empty())
+        {
+            bail!("malformed stack ID `{}`: contains empty parts", s);
+        }
+
+        Ok(StackArn {
+            partition: partition.to_owned(),
+            region: region.to_owned(),
+            account: account.to_owned(),
+            stack_name: stack_name.to_owned(),
+            guid: guid.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for StackArn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "arn:{}:cloudformation:{}:{}:stack/{}/{}",
+            self.partition, self.region, self.account, self.stack_name, self.guid
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_valid() {
+        let stack_arn: StackArn =
+            "arn:aws-cn:cloudformation:cn-north-1:123456789012:stack/stack-name/1a2b3c4d-0000-0000-0000-1234567890ab"
+                .parse()
+                .unwrap();
+        assert_eq!(stack_arn.partition(), "aws-cn");
+        assert_eq!(stack_arn.region(), "cn-north-1");
+        assert_eq!(stack_arn.account(), "123456789012");
+        assert_eq!(stack_arn.stack_name(), "stack-name");
+        assert_eq!(stack_arn.guid(), "1a2b3c4d-0000-0000-0000-1234567890ab");
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        let stack_id = "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid";
+        let stack_arn: StackArn = stack_id.parse().unwrap();
+        assert_eq!(stack_arn.to_string(), stack_id);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        for stack_id in &[
+            "",
+            "STACK-ID",
+            "arn:aws:cloudformation:us-east-2:123456789012",
+            "arn:aws:s3:us-east-2:123456789012:stack/stack-name/guid",
+            "urn:aws:cloudformation:us-east-2:123456789012:stack/stack-name/guid",
+            "arn:aws:cloudformation:us-east-2:123456789012:stackset/stack-name/guid",
+            "arn:aws:cloudformation:us-east-2:123456789012:stack/stack-name",
+            "arn:aws:cloudformation::123456789012:stack/stack-name/guid",
+            "arn:aws:cloudformation:us-east-2:123456789012:stack//guid",
+        ] {
+            assert!(
+                stack_id.parse::<StackArn>().is_err(),
+                "expected `{}` to be rejected",
+                stack_id
+            );
+        }
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::Error;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
+use serde_yaml;
+use std::collections::HashSet;
+
+use resource_properties_schema;
+
+/// The logical resource ID used in the snippet if none was specified.
+const DEFAULT_LOGICAL_RESOURCE_ID: &str = "MyCustomResource";
+
+/// The placeholder for the `ServiceToken` property.
+const SERVICE_TOKEN_PLACEHOLDER: &str = "arn:aws:lambda:REGION:ACCOUNT_ID:function:FUNCTION_NAME";
+
+/// A sample AWS CloudFormation template snippet, showing how to declare a custom resource.
+///
+/// The snippet contains the custom resource with its `ServiceToken` and all of its properties, each
+/// set to a placeholder value matching the type of the property. If the type of the data your
+/// resource responds with is known, the snippet also contains an output for every key of the data,
+/// showing how to retrieve it using `Fn::GetAtt`.
+///
+/// The snippet is built from the JSON Schema of the resource properties and the response data, see
+/// [`resource_properties_schema`] for the caveats that apply. This type is only available with the
+/// `template` feature enabled.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # #[macro_use]
+/// # extern crate schemars;
+/// # #[macro_use]
+/// # extern crate serde;
+/// # use cfn_resource_provider::*;
+/// #[derive(Deserialize, JsonSchema)]
+/// #[serde(rename_all = "PascalCase")]
+/// struct MyResourceProperties {
+///     bucket_name: String,
+///     ttl: Option<u32>,
+/// }
+///
+/// #[derive(Serialize, JsonSchema)]
+/// struct MyResourceData {
+///     #[serde(rename = "Arn")]
+///     arn: String,
+/// }
+///
+/// # fn main() {
+/// let snippet = TemplateSnippet::new::<MyResourceProperties>("Custom::MyResource")
+///     .with_data::<MyResourceData>();
+/// assert_eq!(snippet.attributes(), &["Arn".to_owned()]);
+/// println!("{}", snippet.to_yaml().unwrap());
+/// # }
+/// ```
+///
+/// [`resource_properties_schema`]: fn.resource_properties_schema.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateSnippet {
+    resource_type: String,
+    logical_resource_id: String,
+    properties: Map<String, Value>,
+    attributes: Vec<String>,
+}
+
+impl TemplateSnippet {
+    /// Creates the snippet for the custom resource of type `resource_type`, with the properties
+    /// described by `P`.
+    pub fn new<P>(resource_type: &str) -> TemplateSnippet
+    where
+        P: JsonSchema,
+    {
+        let schema = resource_properties_schema::<P>();
+        let mut properties = match placeholder(&schema, &Schema::Object(schema.schema.clone())) {
+            Value::Object(properties) => properties,
+            _ => Map::new(),
+        };
+        properties.insert(
+            "ServiceToken".to_owned(),
+            Value::String(SERVICE_TOKEN_PLACEHOLDER.to_owned()),
+        );
+
+        TemplateSnippet {
+            resource_type: resource_type.to_owned(),
+            logical_resource_id: DEFAULT_LOGICAL_RESOURCE_ID.to_owned(),
+            properties,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Adds outputs for the keys of the data `S` the custom resource responds with, which can be
+    /// retrieved using `Fn::GetAtt`.
+    pub fn with_data<S>(mut self) -> TemplateSnippet
+    where
+        S: JsonSchema,
+    {
+        let schema = SchemaGenerator::default().into_root_schema_for::<S>();
+        self.attributes = match schema.schema.object {
+            Some(ref object) => object.properties.keys().cloned().collect(),
+            None => Vec::new(),
+        };
+        self
+    }
+
+    /// Sets the logical resource ID used in the snippet, `MyCustomResource` by default.
+    pub fn with_logical_resource_id(mut self, logical_resource_id: &str) -> TemplateSnippet {
+        self.logical_resource_id = logical_resource_id.to_owned();
+        self
+    }
+
+    /// The keys of the data the custom resource responds with, which can be retrieved using
+    /// `Fn::GetAtt`.
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
+    }
+
+    /// The snippet as a JSON value, consisting of the `Resources` and, if there are attributes,
+    /// the `Outputs` section.
+    pub fn to_value(&self) -> Value {
+        let mut snippet = json!({
+            "Resources": {
+                self.logical_resource_id.clone(): {
+                    "Type": self.resource_type,
+                    "Properties": self.properties,
+                },
+            },
+        });
+        if !self.attributes.is_empty() {
+            let outputs: Map<String, Value> = self
+                .attributes
+                .iter()
+                .map(|attribute| {
+                    (
+                        format!("{}{}", self.logical_resource_id, attribute),
+                        json!({
+                            "Value": {
+                                "Fn::GetAtt": [self.logical_resource_id, attribute],
+                            },
+                        }),
+                    )
+                })
+                .collect();
+            snippet["Outputs"] = Value::Object(outputs);
+        }
+        snippet
+    }
+
+    /// The snippet as a pretty-printed JSON document.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_value()).expect("a JSON value is always serializable")
+    }
+
+    /// The snippet as a YAML document.
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        Ok(serde_yaml::to_string(&self.to_value())?)
+    }
+}
+
+/// Creates a placeholder value matching the given schema, resolving references through the root
+/// schema.
+fn placeholder(root: &RootSchema, schema: &Schema) -> Value {
+    placeholder_inner(root, schema, &mut HashSet::new())
+}
+
+fn placeholder_inner<'a>(
+    root: &'a RootSchema,
+    schema: &'a Schema,
+    visiting: &mut HashSet<&'a str>,
+) -> Value {
+    let schema = match schema {
+        Schema::Object(ref schema) => schema,
+        Schema::Bool(_) => return Value::String("<value>".to_owned()),
+    };
+
+    if let Some(ref reference) = schema.reference {
+        let name = reference.rsplit('/').next().unwrap_or_default();
+        // Recursive types would otherwise result in an infinitely large placeholder.
+        if !visiting.insert(name) {
+            return Value::Null;
+        }
+        let value = match root.definitions.get(name) {
+            Some(definition) => placeholder_inner(root, definition, visiting),
+            None => Value::String("<value>".to_owned()),
+        };
+        visiting.remove(name);
+        return value;
+    }
+    if let Some(ref values) = schema.enum_values {
+        if let Some(value) = values.first() {
+            return value.clone();
+        }
+    }
+    if let Some(ref subschemas) = schema.subschemas {
+        let candidates = subschemas
+            .all_of
+            .iter()
+            .chain(subschemas.any_of.iter())
+            .chain(subschemas.one_of.iter())
+            .flatten();
+        for candidate in candidates {
+            if !is_null(candidate) {
+                return placeholder_inner(root, candidate, visiting);
+            }
+        }
+    }
+
+    match instance_type(schema) {
+        Some(InstanceType::Object) | None if schema.object.is_some() => {
+            let object = schema.object.as_ref().expect("checked to be present");
+            Value::Object(
+                object
+                    .properties
+                    .iter()
+                    .map(|(name, property)| {
+                        (name.clone(), placeholder_inner(root, property, visiting))
+                    })
+                    .collect(),
+            )
+        }
+        Some(InstanceType::Object) => json!({}),
+        Some(InstanceType::Array) => {
+            let item = schema
+                .array
+                .as_ref()
+                .and_then(|array| array.items.as_ref())
+                .and_then(|items| match items {
+                    SingleOrVec::Single(ref item) => Some(&**item),
+                    SingleOrVec::Vec(ref items) => items.first(),
+                });
+            match item {
+                Some(item) => json!([placeholder_inner(root, item, visiting)]),
+                None => json!([]),
+            }
+        }
+        Some(InstanceType::String) => Value::String("<string>".to_owned()),
+        Some(InstanceType::Integer) => json!(0),
+        Some(InstanceType::Number) => json!(0.0),
+        Some(InstanceType::Boolean) => json!(false),
+        Some(InstanceType::Null) | None => Value::String("<value>".to_owned()),
+    }
+}
+
+/// Retrieves the first non-null instance type of a schema, as optional values are described by
+/// multiple instance types including `null`.
+fn instance_type(schema: &SchemaObject) -> Option<InstanceType> {
+    match schema.instance_type {
+        Some(SingleOrVec::Single(ref instance_type)) => Some(**instance_type),
+        Some(SingleOrVec::Vec(ref instance_types)) => instance_types
+            .iter()
+            .find(|instance_type| **instance_type != InstanceType::Null)
+            .cloned(),
+        None => None,
+    }
+}
+
+/// Checks whether a schema only permits `null`.
+fn is_null(schema: &Schema) -> bool {
+    match schema {
+        Schema::Object(ref schema) => {
+            schema.instance_type.is_some() && instance_type(schema).is_none()
+        }
+        Schema::Bool(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    #[serde(rename_all = "PascalCase")]
+    struct Nested {
+        enabled: bool,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    enum Mode {
+        Fast,
+        Slow,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    #[serde(rename_all = "PascalCase")]
+    struct Properties {
+        bucket_name: String,
+        ttl: Option<u32>,
+        ratio: f64,
+        tags: Vec<String>,
+        nested: Option<Nested>,
+        mode: Mode,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Data {
+        #[serde(rename = "Arn")]
+        arn: String,
+        #[serde(rename = "Name")]
+        name: String,
+    }
+
+    #[test]
+    fn properties_have_placeholders() {
+        let snippet = TemplateSnippet::new::<Properties>("Custom::Bucket");
+        assert!(snippet.attributes().is_empty());
+        assert_eq!(
+            snippet.to_value(),
+            json!({
+                "Resources": {
+                    "MyCustomResource": {
+                        "Type": "Custom::Bucket",
+                        "Properties": {
+                            "ServiceToken": SERVICE_TOKEN_PLACEHOLDER,
+                            "BucketName": "<string>",
+                            "Ttl": 0,
+                            "Ratio": 0.0,
+                            "Tags": ["<string>"],
+                            "Nested": { "Enabled": false },
+                            "Mode": "Fast",
+                        },
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn data_keys_become_outputs() {
+        let snippet = TemplateSnippet::new::<Properties>("Custom::Bucket")
+            .with_data::<Data>()
+            .with_logical_resource_id("Bucket");
+        assert_eq!(snippet.attributes(), &["Arn".to_owned(), "Name".to_owned()]);
+
+        let value = snippet.to_value();
+        assert!(value["Resources"]["Bucket"].is_object());
+        assert_eq!(
+            value["Outputs"],
+            json!({
+                "BucketArn": { "Value": { "Fn::GetAtt": ["Bucket", "Arn"] } },
+                "BucketName": { "Value": { "Fn::GetAtt": ["Bucket", "Name"] } },
+            })
+        );
+    }
+
+    #[test]
+    fn yaml_and_json() {
+        let snippet = TemplateSnippet::new::<Properties>("Custom::Bucket");
+        let yaml = snippet.to_yaml().unwrap();
+        assert_eq!(
+            yaml,
+            r#"---
+Resources:
+  MyCustomResource:
+    Properties:
+      BucketName: "<string>"
+      Mode: Fast
+      Nested:
+        Enabled: false
+      Ratio: 0.0
+      ServiceToken: "arn:aws:lambda:REGION:ACCOUNT_ID:function:FUNCTION_NAME"
+      Tags:
+        - "<string>"
+      Ttl: 0
+    Type: "Custom::Bucket"
+"#
+        );
+        let json: Value = serde_json::from_str(&snippet.to_json()).unwrap();
+        assert_eq!(json, snippet.to_value());
+    }
+
+    #[test]
+    fn recursive_types_terminate() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Node {
+            children: Vec<Node>,
+        }
+
+        let snippet = TemplateSnippet::new::<Node>("Custom::Tree");
+        assert_eq!(
+            snippet.to_value()["Resources"]["MyCustomResource"]["Properties"]["children"],
+            json!([{ "children": [null] }])
+        );
+    }
+}
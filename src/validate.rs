@@ -0,0 +1,492 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::{Error, Fail};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use regex::Regex;
+use serde::ser::Serialize;
+use std::fmt;
+use {process, CfnRequest, PhysicalResourceIdSuffixProvider};
+
+/// Validation of resource properties beyond what deserialization can verify, e.g. value ranges,
+/// patterns or mutually exclusive properties.
+///
+/// Instead of failing on the first problem, implementors report every problem they find to the
+/// given [`Violations`], such that all of them can be reported to AWS CloudFormation at once.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # use cfn_resource_provider::*;
+/// struct MyResourceProperties {
+///     bucket_name: String,
+///     ttl: u32,
+///     kms_key_id: Option<String>,
+///     sse_algorithm: Option<String>,
+/// }
+///
+/// impl Validate for MyResourceProperties {
+///     fn validate(&self, violations: &mut Violations) {
+///         violations.length("BucketName", &self.bucket_name, 3, 63);
+///         violations.ensure(
+///             "BucketName",
+///             self.bucket_name.chars().all(|c| c.is_ascii_lowercase() || c == '-'),
+///             "must only contain lowercase letters and hyphens",
+///         );
+///         violations.range("Ttl", &self.ttl, 1, 3600);
+///         violations.mutually_exclusive(&[
+///             ("KmsKeyId", self.kms_key_id.is_some()),
+///             ("SseAlgorithm", self.sse_algorithm.is_some()),
+///         ]);
+///     }
+/// }
+///
+/// # fn main() {
+/// let properties = MyResourceProperties {
+///     bucket_name: "My_Bucket".to_owned(),
+///     ttl: 0,
+///     kms_key_id: Some("key".to_owned()),
+///     sse_algorithm: Some("AES256".to_owned()),
+/// };
+/// let mut violations = Violations::new();
+/// properties.validate(&mut violations);
+/// assert_eq!(violations.len(), 3);
+/// # }
+/// ```
+///
+/// [`Violations`]: struct.Violations.html
+pub trait Validate {
+    /// Validates `self`, reporting every problem found to `violations`.
+    fn validate(&self, violations: &mut Violations);
+}
+
+impl<V> Validate for Option<V>
+where
+    V: Validate,
+{
+    fn validate(&self, violations: &mut Violations) {
+        if let Some(ref value) = *self {
+            value.validate(violations);
+        }
+    }
+}
+
+impl<V> Validate for Vec<V>
+where
+    V: Validate,
+{
+    fn validate(&self, violations: &mut Violations) {
+        for (index, value) in self.iter().enumerate() {
+            violations.item(index, value);
+        }
+    }
+}
+
+/// A single problem found while validating resource properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    path: String,
+    message: String,
+}
+
+impl Violation {
+    /// The path of the offending property, e.g. `Rules[2].Port`. Empty if the problem concerns the
+    /// resource properties as a whole.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "`{}` {}", self.path, self.message)
+        }
+    }
+}
+
+/// The collection of problems found while validating resource properties.
+///
+/// Properties are identified by their name as specified in the AWS CloudFormation template. The
+/// names of nested properties are prefixed with the path of the property containing them (see
+/// [`nested`] and [`item`]).
+///
+/// [`nested`]: #method.nested
+/// [`item`]: #method.item
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Violations {
+    path: Vec<String>,
+    violations: Vec<Violation>,
+}
+
+impl Violations {
+    /// Creates an empty collection of violations.
+    pub fn new() -> Violations {
+        Violations::default()
+    }
+
+    /// Records a problem with the given property.
+    pub fn add<M>(&mut self, property: &str, message: M)
+    where
+        M: Into<String>,
+    {
+        let path = self.path_of(property);
+        self.violations.push(Violation {
+            path,
+            message: message.into(),
+        });
+    }
+
+    /// Records a problem with the given property if `condition` does not hold.
+    pub fn ensure<M>(&mut self, property: &str, condition: bool, message: M)
+    where
+        M: Into<String>,
+    {
+        if !condition {
+            self.add(property, message);
+        }
+    }
+
+    /// Records a problem if `value` lies outside of the inclusive range from `min` to `max`.
+    pub fn range<T>(&mut self, property: &str, value: &T, min: T, max: T)
+    where
+        T: PartialOrd + fmt::Display,
+    {
+        if *value < min || *value > max {
+            self.add(
+                property,
+                format!("must be between {} and {}, found {}", min, max, value),
+            );
+        }
+    }
+
+    /// Records a problem if the number of characters in `value` lies outside of the inclusive range
+    /// from `min` to `max`.
+    pub fn length(&mut self, property: &str, value: &str, min: usize, max: usize) {
+        let length = value.chars().count();
+        if length < min || length > max {
+            self.add(
+                property,
+                format!(
+                    "must be between {} and {} characters long, found {}",
+                    min, max, length
+                ),
+            );
+        }
+    }
+
+    /// Records a problem with the given message if `value` does not match `pattern`. Anchor the
+    /// pattern with `^` and `$` to require the whole value to match.
+    pub fn pattern<M>(&mut self, property: &str, value: &str, pattern: &Regex, message: M)
+    where
+        M: Into<String>,
+    {
+        if !pattern.is_match(value) {
+            self.add(property, message);
+        }
+    }
+
+    /// Records a problem if more than one of the given properties is specified. Every property is
+    /// given by its name and whether it is specified.
+    pub fn mutually_exclusive(&mut self, properties: &[(&str, bool)]) {
+        let specified: Vec<_> = properties
+            .iter()
+            .filter(|&&(_, specified)| specified)
+            .map(|&(name, _)| format!("`{}`", self.path_of(name)))
+            .collect();
+        if specified.len() > 1 {
+            self.add(
+                "",
+                format!(
+                    "only one of the properties {} may be specified",
+                    specified.join(", ")
+                ),
+            );
+        }
+    }
+
+    /// Validates the nested `value` of the given property, prefixing the paths of all problems
+    /// found with the name of the property.
+    pub fn nested<V>(&mut self, property: &str, value: &V)
+    where
+        V: Validate + ?Sized,
+    {
+        self.path.push(property.to_owned());
+        value.validate(self);
+        self.path.pop();
+    }
+
+    /// Validates the list item `value` at `index`, suffixing the path of the list with the index.
+    pub fn item<V>(&mut self, index: usize, value: &V)
+    where
+        V: Validate + ?Sized,
+    {
+        self.path.push(format!("[{}]", index));
+        value.validate(self);
+        self.path.pop();
+    }
+
+    /// The number of problems found.
+    pub fn len(&self) -> usize {
+        self.violations.len()
+    }
+
+    /// Whether no problems were found.
+    pub fn is_empty(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// The problems found.
+    pub fn iter(&self) -> ::std::slice::Iter<'_, Violation> {
+        self.violations.iter()
+    }
+
+    /// Turns the collection into an error if any problems were found.
+    pub fn into_result(self) -> Result<(), ValidationError> {
+        if self.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError {
+                violations: self.violations,
+            })
+        }
+    }
+
+    fn path_of(&self, property: &str) -> String {
+        let mut path = String::new();
+        for segment in self.path.iter().map(String::as_str).chain(Some(property)) {
+            if segment.is_empty() {
+                continue;
+            }
+            if !path.is_empty() && !segment.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+}
+
+/// The error returned if resource properties are invalid, listing every problem found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    violations: Vec<Violation>,
+}
+
+impl ValidationError {
+    /// The problems found, of which there is at least one.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid resource properties: ")?;
+        for (index, violation) in self.violations.iter().enumerate() {
+            if index > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for ValidationError {}
+
+impl<P> CfnRequest<P>
+where
+    P: Validate,
+{
+    /// Validates the resource properties of the request.
+    ///
+    /// Delete requests are never rejected, as invalid properties must not prevent a resource from
+    /// being deleted.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        match *self {
+            CfnRequest::Create {
+                ref resource_properties,
+                ..
+            }
+            | CfnRequest::Update {
+                ref resource_properties,
+                ..
+            } => {
+                let mut violations = Violations::new();
+                resource_properties.validate(&mut violations);
+                violations.into_result()
+            }
+            CfnRequest::Delete { .. } => Ok(()),
+        }
+    }
+}
+
+/// Process an AWS CloudFormation custom resource request with resource properties that implement
+/// [`Validate`].
+///
+/// This behaves like [`process`], but validates the resource properties before invoking your
+/// closure. If they are invalid, your closure is not invoked and AWS CloudFormation is informed
+/// about every problem found.
+///
+/// [`Validate`]: trait.Validate.html
+/// [`process`]: fn.process.html
+pub fn process_validated<F, R, P, S>(
+    f: F,
+) -> impl Fn(CfnRequest<P>) -> Box<dyn Future<Item = Option<S>, Error = Error> + Send>
+where
+    F: Fn(CfnRequest<P>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = Option<S>, Error = Error> + Send + 'static,
+    R::Future: Send,
+    S: Serialize + Send + 'static,
+    P: Validate + PhysicalResourceIdSuffixProvider + Send + 'static,
+{
+    process(move |request: CfnRequest<P>| match request.validate() {
+        Ok(()) => Either::A(f(request).into_future()),
+        Err(e) => Either::B(future::err(e.into())),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Rule {
+        port: u16,
+        protocol: String,
+    }
+
+    impl Validate for Rule {
+        fn validate(&self, violations: &mut Violations) {
+            violations.range("Port", &self.port, 1, 1024);
+            violations.ensure(
+                "Protocol",
+                self.protocol == "tcp" || self.protocol == "udp",
+                "must be either `tcp` or `udp`",
+            );
+        }
+    }
+
+    struct Properties {
+        name: String,
+        rules: Vec<Rule>,
+        default_rule: Option<Rule>,
+        cidr: Option<String>,
+        prefix_list: Option<String>,
+    }
+
+    impl Validate for Properties {
+        fn validate(&self, violations: &mut Violations) {
+            violations.length("Name", &self.name, 1, 8);
+            violations.nested("Rules", &self.rules);
+            violations.nested("DefaultRule", &self.default_rule);
+            violations.mutually_exclusive(&[
+                ("Cidr", self.cidr.is_some()),
+                ("PrefixList", self.prefix_list.is_some()),
+            ]);
+        }
+    }
+
+    fn rule(port: u16, protocol: &str) -> Rule {
+        Rule {
+            port,
+            protocol: protocol.to_owned(),
+        }
+    }
+
+    fn validate(properties: &Properties) -> Violations {
+        let mut violations = Violations::new();
+        properties.validate(&mut violations);
+        violations
+    }
+
+    #[test]
+    fn valid_properties() {
+        let violations = validate(&Properties {
+            name: "name".to_owned(),
+            rules: vec![rule(22, "tcp")],
+            default_rule: None,
+            cidr: Some("10.0.0.0/8".to_owned()),
+            prefix_list: None,
+        });
+        assert!(violations.is_empty());
+        assert_eq!(violations.into_result(), Ok(()));
+    }
+
+    #[test]
+    fn collects_all_violations_with_paths() {
+        let violations = validate(&Properties {
+            name: "too-long-name".to_owned(),
+            rules: vec![rule(22, "tcp"), rule(0, "icmp")],
+            default_rule: Some(rule(2048, "udp")),
+            cidr: Some("10.0.0.0/8".to_owned()),
+            prefix_list: Some("pl-1234".to_owned()),
+        });
+        let paths: Vec<_> = violations.iter().map(Violation::path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "Name",
+                "Rules[1].Port",
+                "Rules[1].Protocol",
+                "DefaultRule.Port",
+                ""
+            ]
+        );
+
+        let error = violations.into_result().unwrap_err();
+        assert_eq!(error.violations().len(), 5);
+        assert_eq!(
+            error.to_string(),
+            "invalid resource properties: \
+             `Name` must be between 1 and 8 characters long, found 13; \
+             `Rules[1].Port` must be between 1 and 1024, found 0; \
+             `Rules[1].Protocol` must be either `tcp` or `udp`; \
+             `DefaultRule.Port` must be between 1 and 1024, found 2048; \
+             only one of the properties `Cidr`, `PrefixList` may be specified"
+        );
+    }
+
+    #[test]
+    fn delete_requests_are_not_validated() {
+        let request = CfnRequest::Delete {
+            request_id: String::new(),
+            response_url: String::new(),
+            resource_type: String::new(),
+            logical_resource_id: String::new(),
+            stack_id: String::new(),
+            physical_resource_id: String::new(),
+            resource_properties: rule(0, "icmp"),
+        };
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn pattern() {
+        let pattern = Regex::new("^[a-z][a-z0-9-]*$").unwrap();
+        let mut violations = Violations::new();
+        violations.pattern("Name", "valid-name", &pattern, "must be lowercase");
+        violations.pattern("Name", "Invalid-Name", &pattern, "must be lowercase");
+        violations.pattern("Name", "", &pattern, "must be lowercase");
+
+        let violations: Vec<_> = violations.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            violations,
+            vec!["`Name` must be lowercase", "`Name` must be lowercase"]
+        );
+    }
+}
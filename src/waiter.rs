@@ -0,0 +1,328 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+use failure::{Error, Fail};
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+
+/// The longest interval the backoff increases the interval to, even without a maximum interval.
+/// This keeps the time of the next attempt from overflowing. (`u32::MAX` seconds, spelled out as
+/// the associated constant is not available on the minimum supported Rust version.)
+const LONGEST_INTERVAL: Duration = Duration::from_secs(4_294_967_295);
+
+/// Repeatedly polls an external resource until it is ready, waiting between the attempts.
+///
+/// The waiter gives up once the maximum number of attempts is reached, or if the next attempt
+/// would happen after the deadline. If your handler runs in AWS Lambda, you should set the deadline
+/// to the deadline of the invocation: the waiter keeps a safety margin to the deadline (five
+/// seconds by default), such that there is enough time left to send the `FAILED` response if the
+/// resource does not become ready in time.
+///
+/// The waiter relies on the timer of the [Tokio] runtime, which is available to handlers run
+/// through [`process`] in AWS Lambda.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate cfn_resource_provider;
+/// # extern crate failure;
+/// # extern crate futures;
+/// # use cfn_resource_provider::*;
+/// # use failure::Error;
+/// # use futures::Future;
+/// # use std::time::{Duration, Instant};
+/// # fn describe_cluster() -> Result<&'static str, Error> { Ok("available") }
+/// # fn main() {
+/// # let deadline = Instant::now() + Duration::from_secs(900);
+/// let cluster_available = Waiter::new()
+///     .interval(Duration::from_secs(10))
+///     .backoff(1.5)
+///     .max_interval(Duration::from_secs(60))
+///     .deadline(deadline)
+///     .wait(|| {
+///         describe_cluster().map(|status| if status == "available" { Some(()) } else { None })
+///     });
+/// # }
+/// ```
+///
+/// [Tokio]: https://docs.rs/tokio/0.1
+/// [`process`]: fn.process.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waiter {
+    interval: Duration,
+    max_interval: Option<Duration>,
+    backoff: f64,
+    max_attempts: Option<u32>,
+    deadline: Option<Instant>,
+    deadline_margin: Duration,
+}
+
+impl Default for Waiter {
+    fn default() -> Waiter {
+        Waiter {
+            interval: Duration::from_secs(5),
+            max_interval: None,
+            backoff: 1.0,
+            max_attempts: None,
+            deadline: None,
+            deadline_margin: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Waiter {
+    /// Creates a waiter polling every five seconds, without a maximum number of attempts or a
+    /// deadline.
+    pub fn new() -> Waiter {
+        Waiter::default()
+    }
+
+    /// Sets the interval to wait after the first attempt.
+    pub fn interval(mut self, interval: Duration) -> Waiter {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the factor the interval is multiplied with after every attempt, `1.0` (i.e. a constant
+    /// interval) by default.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the factor is less than `1.0`, infinite or NaN.
+    pub fn backoff(mut self, factor: f64) -> Waiter {
+        assert!(
+            factor.is_finite() && factor >= 1.0,
+            "the backoff factor has to be a finite number of at least 1.0, found {}",
+            factor
+        );
+        self.backoff = factor;
+        self
+    }
+
+    /// Sets the maximum interval the backoff can increase the interval to.
+    pub fn max_interval(mut self, max_interval: Duration) -> Waiter {
+        self.max_interval = Some(max_interval);
+        self
+    }
+
+    /// Sets the maximum number of attempts.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Waiter {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Sets the deadline, usually the deadline of the AWS Lambda invocation.
+    pub fn deadline(mut self, deadline: Instant) -> Waiter {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the time before the deadline after which no attempts are made, five seconds by default.
+    pub fn deadline_margin(mut self, deadline_margin: Duration) -> Waiter {
+        self.deadline_margin = deadline_margin;
+        self
+    }
+
+    /// Polls until `poll` reports the resource as ready by returning `Some`, resolving to the
+    /// value returned. `None` means that the resource is not ready yet. If `poll` fails, the waiter
+    /// fails with the same error.
+    ///
+    /// If the resource does not become ready in time, the waiter fails with a [`WaiterError`].
+    ///
+    /// [`WaiterError`]: enum.WaiterError.html
+    pub fn wait<F, R, T>(self, poll: F) -> impl Future<Item = T, Error = Error>
+    where
+        F: FnMut() -> R,
+        R: IntoFuture<Item = Option<T>, Error = Error>,
+    {
+        let interval = self.interval.min(LONGEST_INTERVAL);
+        future::loop_fn(
+            (self, poll, 1, interval),
+            |(waiter, mut poll, attempt, interval)| {
+                poll().into_future().and_then(move |ready| {
+                    if let Some(value) = ready {
+                        return Either::A(future::ok(Loop::Break(value)));
+                    }
+                    if let Some(max_attempts) = waiter.max_attempts {
+                        if attempt >= max_attempts {
+                            return Either::A(future::err(
+                                WaiterError::MaxAttempts { attempts: attempt }.into(),
+                            ));
+                        }
+                    }
+                    let next_attempt = Instant::now() + interval;
+                    if let Some(deadline) = waiter.deadline {
+                        if next_attempt + waiter.deadline_margin > deadline {
+                            return Either::A(future::err(
+                                WaiterError::Deadline { attempts: attempt }.into(),
+                            ));
+                        }
+                    }
+
+                    let next_interval = waiter.next_interval(interval);
+                    Either::B(
+                        Delay::new(next_attempt).map_err(Error::from).map(move |_| {
+                            Loop::Continue((waiter, poll, attempt + 1, next_interval))
+                        }),
+                    )
+                })
+            },
+        )
+    }
+
+    fn next_interval(&self, interval: Duration) -> Duration {
+        let max_interval = self.max_interval.map_or(LONGEST_INTERVAL, |max_interval| {
+            max_interval.min(LONGEST_INTERVAL)
+        });
+        // Multiplying the seconds instead of the duration itself cannot overflow.
+        let next_interval = interval.as_secs_f64() * self.backoff;
+        if next_interval >= max_interval.as_secs_f64() {
+            max_interval
+        } else {
+            Duration::from_secs_f64(next_interval)
+        }
+    }
+}
+
+/// The error returned if a [`Waiter`] gives up before the resource becomes ready.
+///
+/// [`Waiter`]: struct.Waiter.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaiterError {
+    /// The resource was not ready after the maximum number of attempts.
+    MaxAttempts {
+        /// The number of attempts made.
+        attempts: u32,
+    },
+    /// The resource was not ready before the deadline.
+    Deadline {
+        /// The number of attempts made.
+        attempts: u32,
+    },
+}
+
+impl fmt::Display for WaiterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WaiterError::MaxAttempts { attempts } => write!(
+                f,
+                "the resource was not ready after the maximum of {} attempts",
+                attempts
+            ),
+            WaiterError::Deadline { attempts } => write!(
+                f,
+                "the resource was not ready before the deadline, after {} attempts",
+                attempts
+            ),
+        }
+    }
+}
+
+impl Fail for WaiterError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use tokio_core::reactor::Core;
+
+    fn waiter() -> Waiter {
+        Waiter::new()
+            .interval(Duration::from_millis(1))
+            .deadline_margin(Duration::from_millis(0))
+    }
+
+    #[test]
+    fn ready_after_attempts() {
+        let attempts = Cell::new(0);
+        let result = Core::new().unwrap().run(waiter().max_attempts(5).wait(|| {
+            attempts.set(attempts.get() + 1);
+            Ok(if attempts.get() == 3 {
+                Some("ready")
+            } else {
+                None
+            })
+        }));
+        assert_eq!(result.unwrap(), "ready");
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn max_attempts() {
+        let result = Core::new()
+            .unwrap()
+            .run(waiter().max_attempts(3).wait(|| Ok(None::<()>)));
+        let error = result.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<WaiterError>(),
+            Some(&WaiterError::MaxAttempts { attempts: 3 })
+        );
+    }
+
+    #[test]
+    fn deadline() {
+        let result = Core::new().unwrap().run(
+            waiter()
+                .interval(Duration::from_millis(20))
+                .deadline(Instant::now() + Duration::from_millis(50))
+                .wait(|| Ok(None::<()>)),
+        );
+        match result.unwrap_err().downcast_ref::<WaiterError>() {
+            Some(&WaiterError::Deadline { attempts }) => assert!(attempts >= 2),
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn poll_error() {
+        let result = Core::new()
+            .unwrap()
+            .run(waiter().wait(|| Err::<Option<()>, _>(format_err!("describe failed"))));
+        assert_eq!(result.unwrap_err().to_string(), "describe failed");
+    }
+
+    #[test]
+    fn backoff() {
+        let waiter = Waiter::new()
+            .backoff(2.0)
+            .max_interval(Duration::from_secs(15));
+        assert_eq!(
+            waiter.next_interval(Duration::from_secs(5)),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            waiter.next_interval(Duration::from_secs(10)),
+            Duration::from_secs(15)
+        );
+    }
+
+    #[test]
+    fn backoff_without_max_interval_does_not_overflow() {
+        let waiter = Waiter::new().backoff(1e300);
+        assert_eq!(
+            waiter.next_interval(Duration::from_secs(5)),
+            LONGEST_INTERVAL
+        );
+        assert_eq!(waiter.next_interval(LONGEST_INTERVAL), LONGEST_INTERVAL);
+    }
+
+    #[test]
+    #[should_panic(expected = "the backoff factor has to be a finite number of at least 1.0")]
+    fn backoff_below_one() {
+        Waiter::new().backoff(0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "the backoff factor has to be a finite number of at least 1.0")]
+    fn backoff_nan() {
+        Waiter::new().backoff("NaN".parse().unwrap());
+    }
+}
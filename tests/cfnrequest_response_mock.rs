@@ -8,15 +8,32 @@
 
 extern crate cfn_resource_provider;
 
+#[macro_use]
 extern crate failure;
+extern crate futures;
 #[macro_use]
 extern crate lazy_static;
 extern crate mockito;
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
 extern crate tokio_core;
+extern crate tracing;
 
 use cfn_resource_provider::*;
 use failure::Error;
+use futures::sync::oneshot;
+use futures::{future, Future};
 use mockito::Matcher;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio_core::reactor::Core;
 
 // TODO: identify the actual responses sent by AWS CloudFormation/S3
@@ -119,3 +136,1144 @@ fn cfnrequest_update_ignored_200() {
 fn cfnrequest_update_ignored_403() {
     _simple_test_403(CFNREQUEST_UPDATE_IGNORED.clone());
 }
+
+/// Starts a minimal HTTP server that accepts a single request, answers it with `200 OK` and
+/// forwards the request body through the returned channel. This allows inspecting the exact
+/// response that was sent to AWS CloudFormation.
+fn _capture_response() -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/presigned-url", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let mut header = line.splitn(2, ':');
+            if header
+                .next()
+                .unwrap()
+                .eq_ignore_ascii_case("content-length")
+            {
+                content_length = header.next().unwrap().trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        sender.send(String::from_utf8(body).unwrap()).unwrap();
+    });
+    (url, receiver)
+}
+
+fn _create_request(response_url: String) -> CfnRequest<Ignored> {
+    CfnRequest::Create {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "REQUEST-TYPE".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        resource_properties: Ignored,
+    }
+}
+
+#[test]
+fn cfnrequest_create_success_response() {
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process(|_event: CfnRequest<Ignored>| {
+        Ok(Some(json!({ "Key": "Value" })))
+    })(_create_request(response_url));
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_ok());
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Success);
+    assert_eq!(response.reason(), None);
+    assert_eq!(response.data(), Some(&json!({ "Key": "Value" })));
+}
+
+#[test]
+fn cfnrequest_create_failed_response() {
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process(|_event: CfnRequest<Ignored>| {
+        Err::<Option<()>, _>(format_err!("creation failed"))
+    })(_create_request(response_url));
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_err());
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Failed);
+    assert_eq!(response.reason(), Some("creation failed"));
+    assert_eq!(response.data(), None);
+}
+
+/// This is meant as a type-checking test: `process` must not require the resource properties to be
+/// cloneable, since it is not supposed to clone them.
+#[test]
+fn cfnrequest_create_properties_not_cloned() {
+    struct NotClone;
+    impl PhysicalResourceIdSuffixProvider for NotClone {
+        fn physical_resource_id_suffix(&self) -> String {
+            String::new()
+        }
+    }
+
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process(|_event: CfnRequest<NotClone>| Ok(None::<()>))(
+        CfnRequest::Create {
+            request_id: "REQUEST-ID".to_owned(),
+            response_url,
+            resource_type: "REQUEST-TYPE".to_owned(),
+            logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+            stack_id: "STACK-ID".to_owned(),
+            resource_properties: NotClone,
+        },
+    );
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_ok());
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Success);
+}
+
+#[test]
+fn cfnrequest_create_with_strategy() {
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process_with_strategy(
+        StackNameStrategy,
+        |_event: CfnRequest<Ignored>| Ok(None::<()>),
+    )(CfnRequest::Create {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "REQUEST-TYPE".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "arn:aws:cloudformation:us-east-2:123456789012:stack/STACK-NAME/GUID".to_owned(),
+        resource_properties: Ignored,
+    });
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_ok());
+
+    match serde_json::from_str(&receiver.recv().unwrap()).unwrap() {
+        CfnResponse::Success {
+            physical_resource_id,
+            ..
+        } => assert!(physical_resource_id.starts_with("STACK-NAME-LOGICAL-RESOURCE-ID-")),
+        response => panic!("unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn cfnrequest_create_invalid_properties() {
+    struct Properties;
+    impl PhysicalResourceIdSuffixProvider for Properties {
+        fn physical_resource_id_suffix(&self) -> String {
+            String::new()
+        }
+    }
+    impl Validate for Properties {
+        fn validate(&self, violations: &mut Violations) {
+            violations.add("Name", "must not be empty");
+            violations.add("Size", "must be positive");
+        }
+    }
+
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process_validated(|_event: CfnRequest<Properties>| {
+        panic!("the handler must not be invoked for invalid properties");
+        #[allow(unreachable_code)]
+        Ok(None::<()>)
+    })(CfnRequest::Create {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "REQUEST-TYPE".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        resource_properties: Properties,
+    });
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_err());
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Failed);
+    assert_eq!(
+        response.reason(),
+        Some("invalid resource properties: `Name` must not be empty; `Size` must be positive")
+    );
+}
+
+#[test]
+fn cfnrequest_malformed_json_failed_response() {
+    #[derive(Debug, Deserialize)]
+    struct Properties {
+        #[serde(rename = "Size")]
+        _size: u32,
+    }
+    impl SensitiveProperties for Properties {
+        fn sensitive_values(&self, _values: &mut SensitiveValues) {}
+    }
+    impl PhysicalResourceIdSuffixProvider for Properties {
+        fn physical_resource_id_suffix(&self) -> String {
+            String::new()
+        }
+    }
+
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process_json(cfn_resource_provider::process(
+        |_event: CfnRequest<Properties>| Ok(None::<()>),
+    ))(json!({
+        "RequestType": "Create",
+        "RequestId": "REQUEST-ID",
+        "ResponseURL": response_url,
+        "ResourceType": "Custom::MyCustomResourceType",
+        "LogicalResourceId": "LOGICAL-RESOURCE-ID",
+        "StackId": "STACK-ID",
+        "ResourceProperties": { "Size": "large" },
+    }));
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_err());
+
+    match serde_json::from_str(&receiver.recv().unwrap()).unwrap() {
+        CfnResponse::Failed {
+            reason,
+            request_id,
+            physical_resource_id,
+            ..
+        } => {
+            assert!(reason.starts_with(
+                "failed to deserialize the request: ResourceProperties.Size: invalid type"
+            ));
+            assert_eq!(request_id, "REQUEST-ID");
+            assert_eq!(physical_resource_id, "LOGICAL-RESOURCE-ID");
+        }
+        response => panic!("unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn cfnrequest_malformed_json_delete_success_response() {
+    #[derive(Debug, Deserialize)]
+    struct Properties {
+        #[serde(rename = "Size")]
+        _size: u32,
+    }
+
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::process_json(|_event: CfnRequest<Properties>| {
+        panic!("the handler must not be invoked for malformed requests");
+        #[allow(unreachable_code)]
+        Ok(None::<()>)
+    })(json!({
+        "RequestType": "Delete",
+        "RequestId": "REQUEST-ID",
+        "ResponseURL": response_url,
+        "ResourceType": "Custom::MyCustomResourceType",
+        "LogicalResourceId": "LOGICAL-RESOURCE-ID",
+        "StackId": "STACK-ID",
+        "PhysicalResourceId": "PHYSICAL-RESOURCE-ID",
+        "ResourceProperties": { "Size": "large" },
+    }));
+
+    let mut core = Core::new().unwrap();
+    assert_eq!(core.run(f).unwrap(), None);
+
+    match serde_json::from_str(&receiver.recv().unwrap()).unwrap() {
+        CfnResponse::Success {
+            request_id,
+            physical_resource_id,
+            ..
+        } => {
+            assert_eq!(request_id, "REQUEST-ID");
+            assert_eq!(physical_resource_id, "PHYSICAL-RESOURCE-ID");
+        }
+        response => panic!("unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn cfnrequest_update_create_only_rejected() {
+    struct Properties {
+        name: String,
+    }
+    impl CreateOnlyProperties for Properties {
+        fn compare_create_only(&self, old: &Self, changes: &mut CreateOnlyChanges) {
+            changes.compare("Name", &self.name, &old.name);
+        }
+    }
+    impl SensitiveProperties for Properties {
+        fn sensitive_values(&self, _values: &mut SensitiveValues) {}
+    }
+    impl PhysicalResourceIdSuffixProvider for Properties {
+        fn physical_resource_id_suffix(&self) -> String {
+            self.name.clone()
+        }
+    }
+
+    let (response_url, receiver) = _capture_response();
+    let f = cfn_resource_provider::enforce_create_only(
+        CreateOnlyPolicy::Reject,
+        cfn_resource_provider::process(|_event: CfnRequest<Properties>| -> Result<_, Error> {
+            panic!("the handler must not be invoked for rejected updates");
+            #[allow(unreachable_code)]
+            Ok(None::<()>)
+        }),
+    )(CfnRequest::Update {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "REQUEST-TYPE".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        physical_resource_id: "PHYSICAL-RESOURCE-ID".to_owned(),
+        resource_properties: Properties {
+            name: "new".to_owned(),
+        },
+        old_resource_properties: Properties {
+            name: "old".to_owned(),
+        },
+    });
+
+    let mut core = Core::new().unwrap();
+    assert!(core.run(f).is_err());
+
+    match serde_json::from_str(&receiver.recv().unwrap()).unwrap() {
+        CfnResponse::Failed {
+            reason,
+            physical_resource_id,
+            ..
+        } => {
+            assert_eq!(
+                reason,
+                "the create-only property `Name` cannot be updated, the resource has to be \
+                 replaced instead"
+            );
+            assert_eq!(physical_resource_id, "PHYSICAL-RESOURCE-ID");
+        }
+        response => panic!("unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn cfnrequest_create_idempotent() {
+    let invocations = Arc::new(AtomicUsize::new(0));
+    let processor = {
+        let invocations = invocations.clone();
+        cfn_resource_provider::process_idempotent(
+            MemoryStore::new(),
+            move |_event: CfnRequest<Ignored>| {
+                invocations.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(json!({ "Key": "Value" })))
+            },
+        )
+    };
+    let mut core = Core::new().unwrap();
+
+    let (response_url, receiver) = _capture_response();
+    assert!(core.run(processor(_create_request(response_url))).is_ok());
+    let first: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+
+    let (response_url, receiver) = _capture_response();
+    assert_eq!(
+        core.run(processor(_create_request(response_url))).unwrap(),
+        None
+    );
+    let second: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    assert_eq!(first, second);
+    assert_eq!(second.data(), Some(&json!({ "Key": "Value" })));
+}
+
+#[test]
+fn cfnrequest_create_idempotent_redacted_reason() {
+    let processor = Provider::new()
+        .redactor(Redactor::common())
+        .reason_format(ReasonFormat::new().max_length(40))
+        .process_idempotent(MemoryStore::new(), |_event: CfnRequest<Ignored>| {
+            Err::<Option<()>, _>(format_err!(
+                "cannot reach postgres://admin:hunter2@db, giving up"
+            ))
+        });
+
+    let (response_url, receiver) = _capture_response();
+    assert!(Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)))
+        .is_err());
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(
+        response.reason(),
+        Some("cannot reach postgres://admin:***@db,...")
+    );
+}
+
+#[test]
+fn cfnrequest_create_idempotent_no_echo_and_metrics() {
+    let buffer = SharedBuffer::default();
+    let processor = Provider::new()
+        .no_echo(true)
+        .metrics(Metrics::with_writer("CustomResources", buffer.clone()))
+        .process_idempotent(MemoryStore::new(), |_event: CfnRequest<Ignored>| {
+            Ok(Some(json!({ "Password": "hunter2" })))
+        });
+
+    let (response_url, receiver) = _capture_response();
+    Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)))
+        .unwrap();
+    let response: Value = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response["NoEcho"], true);
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let document: Value = serde_json::from_str(output.trim_end()).unwrap();
+    assert_eq!(document["Create"], 1);
+    assert_eq!(document["Success"], 1);
+}
+
+#[test]
+fn cfnrequest_create_idempotent_concurrent_duplicate() {
+    let invocations = Arc::new(AtomicUsize::new(0));
+    let (finish, finished) = oneshot::channel::<()>();
+    let processor = {
+        let invocations = invocations.clone();
+        let finished = Mutex::new(Some(finished));
+        cfn_resource_provider::process_idempotent(
+            MemoryStore::new(),
+            move |_event: CfnRequest<Ignored>| -> Box<dyn Future<Item = _, Error = _> + Send> {
+                invocations.fetch_add(1, Ordering::SeqCst);
+                // The first delivery is only done once the duplicate has been processed.
+                match finished.lock().unwrap().take() {
+                    Some(finished) => Box::new(
+                        finished
+                            .map(|_| Some(json!({ "Key": "Value" })))
+                            .map_err(Error::from),
+                    ),
+                    None => Box::new(future::ok(None)),
+                }
+            },
+        )
+    };
+
+    let (response_url, receiver) = _capture_response();
+    let first = processor(_create_request(response_url.clone()));
+    let duplicate = processor(_create_request(response_url)).map(move |result| {
+        finish.send(()).unwrap();
+        result
+    });
+    let (first, duplicate) = Core::new().unwrap().run(first.join(duplicate)).unwrap();
+
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    assert_eq!(first, Some(json!({ "Key": "Value" })));
+    assert_eq!(duplicate, None);
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.data(), Some(&json!({ "Key": "Value" })));
+}
+
+#[test]
+fn cfnrequest_create_idempotent_expired_claim() {
+    let store = Arc::new(MemoryStore::new().claim_timeout(Duration::from_secs(0)));
+    // A previous invocation claimed the request, but ended without recording a response.
+    assert_eq!(store.claim("REQUEST-ID").wait().unwrap(), Claim::Claimed);
+
+    let invocations = Arc::new(AtomicUsize::new(0));
+    let processor = {
+        let invocations = invocations.clone();
+        cfn_resource_provider::process_idempotent(
+            store.clone(),
+            move |_event: CfnRequest<Ignored>| {
+                invocations.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(json!({ "Key": "Value" })))
+            },
+        )
+    };
+
+    let (response_url, receiver) = _capture_response();
+    let result = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)));
+
+    assert_eq!(result.unwrap(), Some(json!({ "Key": "Value" })));
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.data(), Some(&json!({ "Key": "Value" })));
+}
+
+#[test]
+fn cfnrequest_create_idempotent_put_failed() {
+    struct FailingStore(MemoryStore);
+    impl IdempotencyStore for FailingStore {
+        fn get(
+            &self,
+            request_id: &str,
+        ) -> Box<dyn Future<Item = Option<CfnResponse>, Error = Error> + Send> {
+            self.0.get(request_id)
+        }
+        fn claim(&self, request_id: &str) -> Box<dyn Future<Item = Claim, Error = Error> + Send> {
+            self.0.claim(request_id)
+        }
+        fn put(
+            &self,
+            _request_id: &str,
+            _response: &CfnResponse,
+        ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+            Box::new(future::err(format_err!("the store is unavailable")))
+        }
+    }
+
+    let processor = cfn_resource_provider::process_idempotent(
+        FailingStore(MemoryStore::new()),
+        |_event: CfnRequest<Ignored>| Ok(Some(json!({ "Key": "Value" }))),
+    );
+
+    let (response_url, receiver) = _capture_response();
+    let result = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)));
+
+    assert_eq!(result.unwrap(), Some(json!({ "Key": "Value" })));
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.data(), Some(&json!({ "Key": "Value" })));
+}
+
+#[test]
+fn cfnrequest_create_continuable() {
+    let scheduler = Arc::new(LocalScheduler::new());
+    let processor = cfn_resource_provider::process_continuable(
+        scheduler.clone(),
+        |_event: CfnRequest<Ignored>, state: Option<u32>| match state.unwrap_or(0) {
+            attempt if attempt < 3 => Ok(Progress::InProgress(attempt + 1)),
+            attempt => Ok(Progress::Done(Some(json!({ "Attempts": attempt })))),
+        },
+    );
+    let mut core = Core::new().unwrap();
+
+    let (response_url, receiver) = _capture_response();
+    let mut event = serde_json::to_value(_create_request(response_url)).unwrap();
+    let mut invocations = 0;
+    loop {
+        invocations += 1;
+        let result = core.run(processor(event)).unwrap();
+        match scheduler.next() {
+            Some(next) => {
+                assert_eq!(result, None);
+                // Only the final invocation may respond to AWS CloudFormation.
+                assert!(receiver.try_recv().is_err());
+                event = next;
+            }
+            None => {
+                assert_eq!(result, Some(json!({ "Attempts": 3 })));
+                break;
+            }
+        }
+    }
+    assert_eq!(invocations, 4);
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Success);
+    assert_eq!(response.data(), Some(&json!({ "Attempts": 3 })));
+}
+
+#[test]
+fn cfnrequest_continuable_malformed_failed_response() {
+    #[derive(Debug, Deserialize)]
+    struct Properties {
+        #[serde(rename = "Size")]
+        _size: u32,
+    }
+    impl SensitiveProperties for Properties {
+        fn sensitive_values(&self, _values: &mut SensitiveValues) {}
+    }
+    impl PhysicalResourceIdSuffixProvider for Properties {
+        fn physical_resource_id_suffix(&self) -> String {
+            String::new()
+        }
+    }
+
+    let processor = cfn_resource_provider::process_continuable(
+        LocalScheduler::new(),
+        |_event: CfnRequest<Properties>, _state: Option<u32>| {
+            panic!("the handler must not be invoked for malformed events");
+            #[allow(unreachable_code)]
+            Ok(Progress::Done(None::<()>))
+        },
+    );
+    let request = |response_url: String, size: Value| {
+        json!({
+            "RequestType": "Create",
+            "RequestId": "REQUEST-ID",
+            "ResponseURL": response_url,
+            "ResourceType": "Custom::MyCustomResourceType",
+            "LogicalResourceId": "LOGICAL-RESOURCE-ID",
+            "StackId": "STACK-ID",
+            "ResourceProperties": { "Size": size },
+        })
+    };
+    let mut core = Core::new().unwrap();
+
+    let (response_url, receiver) = _capture_response();
+    assert!(core
+        .run(processor(request(response_url, json!("large"))))
+        .is_err());
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Failed);
+    assert!(response
+        .reason()
+        .unwrap()
+        .starts_with("failed to deserialize the request: ResourceProperties.Size: invalid type"));
+
+    let (response_url, receiver) = _capture_response();
+    let continuation = json!({
+        "CfnResourceProviderContinuation": {
+            "Request": request(response_url, json!(1)),
+            "State": "not a number",
+        },
+    });
+    assert!(core.run(processor(continuation)).is_err());
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Failed);
+    assert!(response
+        .reason()
+        .unwrap()
+        .starts_with("failed to deserialize the continuation: invalid type"));
+}
+
+#[test]
+fn cfnrequest_create_wait_timeout_failed_response() {
+    let processor = cfn_resource_provider::process(|_event: CfnRequest<Ignored>| {
+        Waiter::new()
+            .interval(Duration::from_millis(10))
+            .deadline(Instant::now() + Duration::from_millis(100))
+            .deadline_margin(Duration::from_millis(50))
+            .wait(|| Ok(None::<()>))
+            .map(Some)
+    });
+
+    let (response_url, receiver) = _capture_response();
+    let result = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)));
+    let error = result.unwrap_err();
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Failed);
+    assert_eq!(response.reason(), Some(&*error.to_string()));
+    assert!(error
+        .to_string()
+        .starts_with("the resource was not ready before the deadline"));
+}
+
+/// A writer appending to a buffer shared with the test, to capture the metrics written.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn cfnrequest_create_metrics() {
+    let buffer = SharedBuffer::default();
+    let processor = Provider::new()
+        .metrics(Metrics::with_writer("CustomResources", buffer.clone()))
+        .process(|_event: CfnRequest<Ignored>| Err::<Option<()>, _>(format_err!("failed")));
+
+    let (response_url, receiver) = _capture_response();
+    let result = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)));
+    assert!(result.is_err());
+    receiver.recv().unwrap();
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let document: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(
+        document["_aws"]["CloudWatchMetrics"][0]["Namespace"],
+        "CustomResources"
+    );
+    assert_eq!(document["ResourceType"], "REQUEST-TYPE");
+    assert_eq!(document["Create"], 1);
+    assert_eq!(document["Failed"], 1);
+    assert_eq!(document["Success"], 0);
+    assert!(document["HandlerDuration"].is_number());
+    assert!(document["ResponseDuration"].is_number());
+}
+
+/// A span as recorded by the `SpanRecorder`.
+#[derive(Debug)]
+struct RecordedSpan {
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: HashMap<&'static str, String>,
+}
+
+/// A `tracing` subscriber recording the spans created and the span every event was emitted in.
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    events: Arc<Mutex<Vec<Option<&'static str>>>>,
+    event_fields: Arc<Mutex<Vec<HashMap<&'static str, String>>>>,
+    entered: Arc<Mutex<Vec<u64>>>,
+}
+
+impl SpanRecorder {
+    fn current(&self) -> Option<&'static str> {
+        let entered = self.entered.lock().unwrap();
+        entered
+            .last()
+            .map(|id| self.spans.lock().unwrap()[*id as usize - 1].name)
+    }
+}
+
+struct FieldRecorder<'a>(&'a mut HashMap<&'static str, String>);
+
+impl<'a> tracing::field::Visit for FieldRecorder<'a> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &tracing::Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &tracing::span::Attributes) -> tracing::span::Id {
+        let parent = match attributes.parent() {
+            Some(parent) => Some(self.spans.lock().unwrap()[parent.into_u64() as usize - 1].name),
+            None if attributes.is_contextual() => self.current(),
+            None => None,
+        };
+        let mut fields = HashMap::new();
+        attributes.record(&mut FieldRecorder(&mut fields));
+
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(RecordedSpan {
+            name: attributes.metadata().name(),
+            parent,
+            fields,
+        });
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record) {}
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event) {
+        let current = self.current();
+        self.events.lock().unwrap().push(current);
+        let mut fields = HashMap::new();
+        event.record(&mut FieldRecorder(&mut fields));
+        self.event_fields.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, span: &tracing::span::Id) {
+        self.entered.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _span: &tracing::span::Id) {
+        self.entered.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn cfnrequest_create_tracing_spans() {
+    let recorder = SpanRecorder::default();
+    let processor = cfn_resource_provider::process(|_event: CfnRequest<Ignored>| {
+        tracing::info!("creating the resource");
+        Ok(None::<()>)
+    });
+
+    let (response_url, receiver) = _capture_response();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        Core::new()
+            .unwrap()
+            .run(processor(_create_request(response_url)))
+            .unwrap();
+    });
+    receiver.recv().unwrap();
+
+    let spans = recorder.spans.lock().unwrap();
+    let request_span = spans
+        .iter()
+        .find(|span| span.name == "cfn_request")
+        .unwrap();
+    assert_eq!(request_span.parent, None);
+    assert_eq!(request_span.fields["request_type"], "Create");
+    assert_eq!(request_span.fields["request_id"], "REQUEST-ID");
+    assert_eq!(request_span.fields["resource_type"], "REQUEST-TYPE");
+    assert_eq!(
+        request_span.fields["logical_resource_id"],
+        "LOGICAL-RESOURCE-ID"
+    );
+    assert_eq!(request_span.fields["stack_id"], "STACK-ID");
+    for child in &["handler", "send_response"] {
+        let span = spans.iter().find(|span| span.name == *child).unwrap();
+        assert_eq!(span.parent, Some("cfn_request"));
+    }
+
+    // The event describing the request, the one emitted by the handler and the one describing the
+    // response, each within its respective span.
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(
+        *events,
+        vec![Some("cfn_request"), Some("handler"), Some("send_response")]
+    );
+}
+
+#[test]
+fn cfnrequest_create_no_echo_data_not_logged() {
+    let recorder = SpanRecorder::default();
+    let processor = Provider::new()
+        .no_echo(true)
+        .process(|_event: CfnRequest<Ignored>| Ok(Some(json!({ "Password": "hunter2" }))));
+
+    let (response_url, receiver) = _capture_response();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        Core::new()
+            .unwrap()
+            .run(processor(_create_request(response_url)))
+            .unwrap();
+    });
+    let response: Value = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response["NoEcho"], true);
+    assert_eq!(response["Data"]["Password"], "hunter2");
+
+    let event_fields = recorder.event_fields.lock().unwrap();
+    let sent = event_fields
+        .iter()
+        .find(|fields| fields.get("message").map(String::as_str) == Some("sent response"))
+        .unwrap();
+    assert_eq!(sent["data_attributes"], "Password");
+    for fields in event_fields.iter() {
+        assert!(
+            fields.values().all(|value| !value.contains("hunter2")),
+            "secret logged: {:?}",
+            fields
+        );
+    }
+}
+
+#[test]
+fn cfnrequest_create_response_url_refused() {
+    let invoked = Arc::new(AtomicUsize::new(0));
+    let processor = {
+        let invoked = invoked.clone();
+        Provider::new()
+            .response_url_policy(ResponseUrlPolicy::s3())
+            .process(move |_event: CfnRequest<Ignored>| {
+                invoked.fetch_add(1, Ordering::SeqCst);
+                Ok(None::<()>)
+            })
+    };
+
+    let (response_url, receiver) = _capture_response();
+    let error = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "the response URL uses the scheme `http`, but only HTTPS is allowed"
+    );
+    assert_eq!(invoked.load(Ordering::SeqCst), 0);
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn cfnrequest_create_idempotent_response_url_refused() {
+    let store = Arc::new(MemoryStore::new());
+    let invoked = Arc::new(AtomicUsize::new(0));
+    let processor = {
+        let invoked = invoked.clone();
+        Provider::new()
+            .response_url_policy(ResponseUrlPolicy::s3())
+            .process_idempotent(store.clone(), move |_event: CfnRequest<Ignored>| {
+                invoked.fetch_add(1, Ordering::SeqCst);
+                Ok(None::<()>)
+            })
+    };
+
+    let (response_url, receiver) = _capture_response();
+    let mut core = Core::new().unwrap();
+    assert!(core.run(processor(_create_request(response_url))).is_err());
+    assert_eq!(invoked.load(Ordering::SeqCst), 0);
+    assert!(receiver.try_recv().is_err());
+    // The request is not recorded, such that it is processed once delivered properly.
+    assert_eq!(core.run(store.get("REQUEST-ID")).unwrap(), None);
+}
+
+#[test]
+fn cfnrequest_malformed_json_response_url_refused() {
+    let (response_url, receiver) = _capture_response();
+    let f = Provider::new()
+        .response_url_policy(ResponseUrlPolicy::s3())
+        .process_json(cfn_resource_provider::process(
+            |_event: CfnRequest<Ignored>| Ok(None::<()>),
+        ))(json!({
+        "RequestType": "Create",
+        "RequestId": "REQUEST-ID",
+        "ResponseURL": response_url,
+        "LogicalResourceId": "LOGICAL-RESOURCE-ID",
+        "StackId": "STACK-ID",
+    }));
+
+    let error = Core::new().unwrap().run(f).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "the response URL uses the scheme `http`, but only HTTPS is allowed"
+    );
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn cfnrequest_create_response_url_allowed_locally() {
+    let processor = Provider::new()
+        .response_url_policy(ResponseUrlPolicy::s3().allow_http().allow_host("127.0.0.1"))
+        .process(|_event: CfnRequest<Ignored>| Ok(None::<()>));
+
+    let (response_url, receiver) = _capture_response();
+    Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)))
+        .unwrap();
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Success);
+}
+
+struct DatabaseProperties {
+    password: String,
+}
+
+impl SensitiveProperties for DatabaseProperties {
+    fn sensitive_values(&self, values: &mut SensitiveValues) {
+        values.add(&self.password);
+    }
+}
+
+#[test]
+fn cfnrequest_create_redacted_reason() {
+    let processor = Provider::new()
+        .strategy(RandomUuidStrategy)
+        .redactor(Redactor::common())
+        .process_sensitive(|_event: CfnRequest<DatabaseProperties>| {
+            Err::<Option<()>, _>(format_err!(
+                "login with hunter2 failed, token=abc123 was rejected"
+            ))
+        });
+
+    let (response_url, receiver) = _capture_response();
+    let request = CfnRequest::Create {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "REQUEST-TYPE".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        resource_properties: DatabaseProperties {
+            password: "hunter2".to_owned(),
+        },
+    };
+    let error = Core::new().unwrap().run(processor(request)).unwrap_err();
+    // Only the reason sent to AWS CloudFormation is redacted.
+    assert_eq!(
+        error.to_string(),
+        "login with hunter2 failed, token=abc123 was rejected"
+    );
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(
+        response.reason(),
+        Some("login with *** failed, token=*** was rejected")
+    );
+}
+
+#[test]
+fn cfnrequest_create_formatted_reason() {
+    let processor = Provider::new()
+        .reason_format(ReasonFormat::new().cause_chain().max_length(40))
+        .process(|_event: CfnRequest<Ignored>| {
+            Err::<Option<()>, _>(
+                failure::err_msg("access denied for the role")
+                    .context("failed to create the bucket")
+                    .into(),
+            )
+        });
+
+    let (response_url, receiver) = _capture_response();
+    let error = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)))
+        .unwrap_err();
+    assert_eq!(error.to_string(), "failed to create the bucket");
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(
+        response.reason(),
+        Some("failed to create the bucket: access d...")
+    );
+}
+
+/// Starts a minimal HTTP server that answers every request with `200 OK`, keeping connections
+/// alive, and counts the connections accepted.
+fn _count_connections() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/presigned-url", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut reader = BufReader::new(stream.unwrap());
+            thread::spawn(move || loop {
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let mut header = line.splitn(2, ':');
+                    if header
+                        .next()
+                        .unwrap()
+                        .eq_ignore_ascii_case("content-length")
+                    {
+                        content_length = header.next().unwrap().trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                reader
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .unwrap();
+            });
+        }
+    });
+    (url, connections)
+}
+
+#[test]
+fn cfnrequest_create_http_client_reused() {
+    let processor = Provider::new().process(|_event: CfnRequest<Ignored>| Ok(None::<()>));
+
+    let (response_url, connections) = _count_connections();
+    let mut core = Core::new().unwrap();
+    for _ in 0..3 {
+        core.run(processor(_create_request(response_url.clone())))
+            .unwrap();
+        // Give the connection the chance to return to the pool, as it has between invocations of a
+        // warm AWS Lambda.
+        core.turn(Some(Duration::from_millis(50)));
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn cfnrequest_create_http_client_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let response_url = format!("http://{}/presigned-url", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        // Accept the connection, but never answer.
+        let _stream = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(10));
+    });
+
+    let processor = Provider::new()
+        .http_client(HttpClient::new().timeout(Duration::from_millis(200)))
+        .process(|_event: CfnRequest<Ignored>| Ok(None::<()>));
+
+    let started = Instant::now();
+    let result = Core::new()
+        .unwrap()
+        .run(processor(_create_request(response_url)));
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+struct BucketProperties;
+
+impl CustomResource for BucketProperties {
+    const RESOURCE_TYPE: &'static str = "Custom::Bucket";
+}
+
+impl SensitiveProperties for BucketProperties {
+    fn sensitive_values(&self, _values: &mut SensitiveValues) {}
+}
+
+impl PhysicalResourceIdSuffixProvider for BucketProperties {
+    fn physical_resource_id_suffix(&self) -> String {
+        String::new()
+    }
+}
+
+#[test]
+fn cfnrequest_delete_other_resource_type_success_response() {
+    let processor = process_custom_resource(|_event: CfnRequest<BucketProperties>| Ok(None::<()>));
+
+    let (response_url, receiver) = _capture_response();
+    let request = CfnRequest::Delete {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "Custom::RenamedBucket".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        physical_resource_id: "PHYSICAL-RESOURCE-ID".to_owned(),
+        resource_properties: BucketProperties,
+    };
+    Core::new().unwrap().run(processor(request)).unwrap();
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Success);
+}
+
+#[test]
+fn cfnrequest_create_other_resource_type_configured_reason() {
+    let processor = Provider::new()
+        .reason_format(ReasonFormat::new().max_length(20))
+        .process_custom_resource(|_event: CfnRequest<BucketProperties>| Ok(None::<()>));
+
+    let (response_url, receiver) = _capture_response();
+    let request = CfnRequest::Create {
+        request_id: "REQUEST-ID".to_owned(),
+        response_url,
+        resource_type: "Custom::Queue".to_owned(),
+        logical_resource_id: "LOGICAL-RESOURCE-ID".to_owned(),
+        stack_id: "STACK-ID".to_owned(),
+        resource_properties: BucketProperties,
+    };
+    assert!(Core::new().unwrap().run(processor(request)).is_err());
+
+    let response: CfnResponse = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+    assert_eq!(response.status(), ResponseStatus::Failed);
+    assert_eq!(response.reason(), Some("unexpected resour..."));
+}